@@ -0,0 +1,262 @@
+//! Scanning and cache benchmarks over synthetic trees.
+//!
+//! Each tree shape is written to a temporary directory once, then scanned
+//! with [`compute_dir_sizes_with_callback`] and round-tripped through a
+//! [`Cache`]. After the timed runs, the peak heap use of a scan and of a cache
+//! load is printed for every shape.
+//!
+//! ```text
+//! cargo bench                      # every shape
+//! cargo bench -- scan/deep         # a single benchmark
+//! RUDU_BENCH_SHAPE=50,3,20,4096 cargo bench -- custom
+//! ```
+//!
+//! `RUDU_BENCH_SHAPE` adds a `custom` shape given as
+//! `width,depth,files,file_size`, see [`Shape`].
+
+use criterion::{criterion_group, BenchmarkId, Criterion, Throughput};
+use humansize::{format_size, DECIMAL};
+use rudu::cache::Cache;
+use rudu::compute_dir_sizes_with_callback;
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::fs::{self, File};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::OnceLock;
+use tempfile::TempDir;
+
+/// Heap allocator that tracks the bytes in use and their peak.
+struct Counting;
+
+static IN_USE: AtomicUsize = AtomicUsize::new(0);
+static PEAK: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for Counting {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let ptr = System.alloc(layout);
+        if !ptr.is_null() {
+            let in_use = IN_USE.fetch_add(layout.size(), Ordering::Relaxed) + layout.size();
+            PEAK.fetch_max(in_use, Ordering::Relaxed);
+        }
+        ptr
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout);
+        IN_USE.fetch_sub(layout.size(), Ordering::Relaxed);
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: Counting = Counting;
+
+/// Peak heap growth while running `f`, and the heap still held by its result.
+fn measure_memory<T>(f: impl FnOnce() -> T) -> (usize, usize) {
+    let before = IN_USE.load(Ordering::Relaxed);
+    PEAK.store(before, Ordering::Relaxed);
+    let result = f();
+    let peak = PEAK.load(Ordering::Relaxed).saturating_sub(before);
+    let retained = IN_USE.load(Ordering::Relaxed).saturating_sub(before);
+    drop(result);
+    (peak, retained)
+}
+
+/// A tree where every directory down to `depth` levels holds `width`
+/// subdirectories and `files` files of `file_size` bytes.
+#[derive(Clone, Copy, Debug)]
+struct Shape {
+    name: &'static str,
+    width: usize,
+    depth: usize,
+    files: usize,
+    file_size: u64,
+}
+
+/// Files at or above this size are written sparse.
+const SPARSE_SIZE: u64 = 1 << 20;
+
+const SHAPES: &[Shape] = &[
+    Shape {
+        name: "wide",
+        width: 5_000,
+        depth: 1,
+        files: 2,
+        file_size: 1_000,
+    },
+    Shape {
+        name: "deep",
+        width: 1,
+        depth: 300,
+        files: 4,
+        file_size: 1_000,
+    },
+    Shape {
+        name: "tiny-files",
+        width: 10,
+        depth: 2,
+        files: 300,
+        file_size: 16,
+    },
+    Shape {
+        name: "huge-files",
+        width: 2,
+        depth: 1,
+        files: 2,
+        file_size: 4 << 30,
+    },
+];
+
+impl Shape {
+    /// The shape described by `RUDU_BENCH_SHAPE`, if set.
+    fn custom() -> Option<Shape> {
+        let spec = std::env::var("RUDU_BENCH_SHAPE").ok()?;
+        let fields: Vec<u64> = spec
+            .split(',')
+            .map(|field| field.trim().parse())
+            .collect::<Result<_, _>>()
+            .unwrap_or_else(|_| panic!("RUDU_BENCH_SHAPE must be numbers, got '{spec}'"));
+        let [width, depth, files, file_size] = fields[..] else {
+            panic!("RUDU_BENCH_SHAPE must be width,depth,files,file_size, got '{spec}'");
+        };
+        Some(Shape {
+            name: "custom",
+            width: width as usize,
+            depth: depth as usize,
+            files: files as usize,
+            file_size,
+        })
+    }
+
+    /// Directories and files in the tree, the unit of scan throughput.
+    fn entries(&self) -> u64 {
+        let dirs: u64 = (0..=self.depth as u32)
+            .map(|level| (self.width as u64).pow(level))
+            .sum();
+        dirs * (1 + self.files as u64)
+    }
+
+    fn write_to(&self, root: &Path) {
+        let mut level = vec![root.to_path_buf()];
+        fs::create_dir_all(root).unwrap();
+        for remaining in (0..=self.depth).rev() {
+            let mut next = Vec::new();
+            for dir in &level {
+                for i in 0..self.files {
+                    let file = File::create(dir.join(format!("file{i}"))).unwrap();
+                    if self.file_size >= SPARSE_SIZE {
+                        file.set_len(self.file_size).unwrap();
+                    } else {
+                        use std::io::Write;
+                        (&file)
+                            .write_all(&vec![b'x'; self.file_size as usize])
+                            .unwrap();
+                    }
+                }
+                if remaining > 0 {
+                    for i in 0..self.width {
+                        let sub = dir.join(format!("d{i}"));
+                        fs::create_dir(&sub).unwrap();
+                        next.push(sub);
+                    }
+                }
+            }
+            level = next;
+        }
+    }
+}
+
+/// A shape written to disk, removed when dropped.
+struct Tree {
+    shape: Shape,
+    root: PathBuf,
+    _dir: TempDir,
+}
+
+/// Every shape, written to disk on first use and shared by all benchmarks.
+fn trees() -> &'static [Tree] {
+    static TREES: OnceLock<Vec<Tree>> = OnceLock::new();
+    TREES.get_or_init(|| {
+        SHAPES
+            .iter()
+            .copied()
+            .chain(Shape::custom())
+            .map(|shape| {
+                let dir = tempfile::tempdir().unwrap();
+                let root = dir.path().join(shape.name);
+                shape.write_to(&root);
+                Tree {
+                    shape,
+                    root,
+                    _dir: dir,
+                }
+            })
+            .collect()
+    })
+}
+
+fn bench_scan(c: &mut Criterion) {
+    let mut group = c.benchmark_group("scan");
+    for tree in trees() {
+        group.throughput(Throughput::Elements(tree.shape.entries()));
+        group.bench_with_input(
+            BenchmarkId::from_parameter(tree.shape.name),
+            &tree.root,
+            |b, root| b.iter(|| compute_dir_sizes_with_callback(root, |_| {})),
+        );
+    }
+    group.finish();
+}
+
+fn bench_cache(c: &mut Criterion) {
+    let mut group = c.benchmark_group("cache");
+    for tree in trees() {
+        let cache_dir = tempfile::tempdir().unwrap();
+        let cache = Cache::with_dir(cache_dir.path()).unwrap();
+        let (sizes, files, _) = compute_dir_sizes_with_callback(&tree.root, |_| {});
+        group.throughput(Throughput::Elements(sizes.len() as u64));
+
+        group.bench_function(BenchmarkId::new("store", tree.shape.name), |b| {
+            b.iter(|| cache.store(&tree.root, &sizes, files).unwrap())
+        });
+        group.bench_function(BenchmarkId::new("load", tree.shape.name), |b| {
+            b.iter(|| cache.retrieve(&tree.root, 3600).unwrap().unwrap())
+        });
+    }
+    group.finish();
+}
+
+/// Print the heap used by a scan and by a cache load of every shape.
+fn report_memory() {
+    println!("\nHeap use (peak during the call / retained by its result):");
+    for tree in trees() {
+        let (scan_peak, scan_kept) =
+            measure_memory(|| compute_dir_sizes_with_callback(&tree.root, |_| {}));
+
+        let cache_dir = tempfile::tempdir().unwrap();
+        let cache = Cache::with_dir(cache_dir.path()).unwrap();
+        let (sizes, files, _) = compute_dir_sizes_with_callback(&tree.root, |_| {});
+        cache.store(&tree.root, &sizes, files).unwrap();
+        drop(sizes);
+        let (load_peak, load_kept) = measure_memory(|| cache.retrieve(&tree.root, 3600));
+
+        println!(
+            "  {:<12} scan {:>10} / {:>10}   cache load {:>10} / {:>10}",
+            tree.shape.name,
+            format_size(scan_peak, DECIMAL),
+            format_size(scan_kept, DECIMAL),
+            format_size(load_peak, DECIMAL),
+            format_size(load_kept, DECIMAL),
+        );
+    }
+}
+
+criterion_group!(benches, bench_scan, bench_cache);
+
+fn main() {
+    benches();
+    // `cargo test --benches` only checks that the benchmarks run
+    if std::env::args().any(|arg| arg == "--bench") {
+        report_memory();
+    }
+    Criterion::default().configure_from_args().final_summary();
+}
@@ -210,6 +210,39 @@ cargo build --release
 ./scripts/benchmark.sh /path/to/test/directory
 \`\`\`
 
+## Regression Benchmarks
+
+The comparison above depends on the directory being scanned. For repeatable
+numbers, \`cargo bench\` runs the criterion suite in \`benches/scan.rs\` on
+synthetic trees written to a temporary directory:
+
+| Shape | Layout |
+|-------|--------|
+| \`wide\` | 5,000 directories side by side, 2 files each |
+| \`deep\` | 301 directories nested in a single chain, 4 files each |
+| \`tiny-files\` | 111 directories holding 300 files of 16 bytes |
+| \`huge-files\` | 3 directories holding two sparse 4 GiB files |
+
+For each shape it measures scan throughput (entries per second through
+\`compute_dir_sizes_with_callback\`), cache store and load time, and prints the
+peak and retained heap of a scan and of a cache load.
+
+\`\`\`bash
+# Everything, comparing against the previous run
+cargo bench
+
+# One benchmark or group
+cargo bench -- scan/deep
+cargo bench -- cache/load
+
+# Add a custom shape: width,depth,files,file_size
+RUDU_BENCH_SHAPE=50,3,20,4096 cargo bench -- custom
+
+# Save a baseline before a change and compare after it
+cargo bench -- --save-baseline before
+cargo bench -- --baseline before
+\`\`\`
+
 ## Notes
 
 - Times may vary based on system specifications, disk speed, and directory structure
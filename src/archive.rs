@@ -0,0 +1,284 @@
+use crate::tree::DirTree;
+use flate2::read::GzDecoder;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, BufReader, Read};
+use std::path::{Component, Path, PathBuf};
+
+/// Archive formats that can be scanned as virtual directories.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ArchiveKind {
+    /// Uncompressed `.tar`
+    Tar,
+    /// Gzip-compressed `.tar.gz` or `.tgz`
+    TarGz,
+    /// `.zip`, which records compressed sizes per member
+    Zip,
+}
+
+impl ArchiveKind {
+    /// Detect the archive format from a file name's extension.
+    pub fn from_path(path: &Path) -> Option<Self> {
+        let name = path.file_name()?.to_str()?.to_ascii_lowercase();
+        if name.ends_with(".tar.gz") || name.ends_with(".tgz") {
+            Some(ArchiveKind::TarGz)
+        } else if name.ends_with(".tar") {
+            Some(ArchiveKind::Tar)
+        } else if name.ends_with(".zip") {
+            Some(ArchiveKind::Zip)
+        } else {
+            None
+        }
+    }
+}
+
+/// Sizes of an archive's members, aggregated as if the archive were a directory.
+#[derive(Clone)]
+pub struct ArchiveScan {
+    /// Uncompressed member sizes, rooted at the archive's own path
+    pub tree: DirTree,
+    /// Compressed size of each virtual directory, as far as the format
+    /// records it.
+    ///
+    /// Zip archives store a compressed size per member, so every directory
+    /// is listed. Tar archives are compressed as a whole, so only the archive
+    /// root is listed, with its size on disk.
+    pub compressed: HashMap<PathBuf, u64>,
+}
+
+/// Read the member list of the archive at `path` into a virtual directory tree.
+///
+/// Members are placed under `path` itself, so `backup.tar.gz` containing
+/// `usr/lib/libfoo.so` yields the directory `backup.tar.gz/usr/lib`. Member
+/// names are sanitized: `.`, `..` and leading `/` components are dropped.
+/// Only regular files contribute sizes; links and special files are ignored.
+///
+/// # Example
+///
+/// ```rust,no_run
+/// use std::path::Path;
+/// use rudu::archive::scan_archive;
+///
+/// let scan = scan_archive(Path::new("backup.zip")).unwrap();
+/// let root = scan.tree.root();
+/// println!(
+///     "{} bytes uncompressed, {:?} compressed",
+///     scan.tree.size(root),
+///     scan.compressed.get(scan.tree.root_path())
+/// );
+/// ```
+pub fn scan_archive(path: &Path) -> io::Result<ArchiveScan> {
+    let kind = ArchiveKind::from_path(path).ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "unsupported archive format (expected .tar, .tar.gz, .tgz or .zip)",
+        )
+    })?;
+    let file = File::open(path)?;
+    let on_disk = file.metadata()?.len();
+
+    let mut scan = match kind {
+        ArchiveKind::Tar => read_tar(BufReader::new(file), path)?,
+        ArchiveKind::TarGz => read_tar(GzDecoder::new(BufReader::new(file)), path)?,
+        ArchiveKind::Zip => read_zip(file, path)?,
+    };
+    scan.tree.aggregate();
+    // Tar archives are compressed as a whole, so only their size on disk is known
+    scan.compressed.entry(path.to_path_buf()).or_insert(on_disk);
+    Ok(scan)
+}
+
+fn read_tar(reader: impl Read, root: &Path) -> io::Result<ArchiveScan> {
+    let mut tree = DirTree::new(root);
+    for entry in tar::Archive::new(reader).entries()? {
+        let entry = entry?;
+        let member = match member_path(root, &entry.path()?) {
+            Some(member) => member,
+            None => continue,
+        };
+
+        let entry_type = entry.header().entry_type();
+        if entry_type.is_dir() {
+            tree.insert_dir(&member);
+        } else if entry_type.is_file() {
+            tree.add_file(&member, entry.header().size()?);
+        }
+    }
+
+    Ok(ArchiveScan {
+        tree,
+        compressed: HashMap::new(),
+    })
+}
+
+fn read_zip(file: File, root: &Path) -> io::Result<ArchiveScan> {
+    let mut archive = zip::ZipArchive::new(BufReader::new(file))?;
+    let mut tree = DirTree::new(root);
+    let mut compressed_files: HashMap<PathBuf, u64> = HashMap::new();
+
+    for index in 0..archive.len() {
+        // Raw access reads only the central directory record, without inflating
+        let member = archive.by_index_raw(index)?;
+        let path = match member_path(root, Path::new(member.name())) {
+            Some(path) => path,
+            None => continue,
+        };
+
+        if member.is_dir() {
+            tree.insert_dir(&path);
+        } else if member.is_file() && tree.add_file(&path, member.size()) {
+            if let Some(parent) = path.parent() {
+                *compressed_files.entry(parent.to_path_buf()).or_insert(0) +=
+                    member.compressed_size();
+            }
+        }
+    }
+
+    // Roll compressed sizes up to every ancestor, like the uncompressed tree
+    let mut compressed: HashMap<PathBuf, u64> = HashMap::new();
+    for (dir, bytes) in compressed_files {
+        for ancestor in dir.ancestors().take_while(|a| a.starts_with(root)) {
+            *compressed.entry(ancestor.to_path_buf()).or_insert(0) += bytes;
+        }
+    }
+
+    Ok(ArchiveScan { tree, compressed })
+}
+
+/// Place a member name under `root`, keeping only normal path components.
+fn member_path(root: &Path, name: &Path) -> Option<PathBuf> {
+    let mut path = root.to_path_buf();
+    let mut levels = 0;
+    for component in name.components() {
+        if let Component::Normal(level) = component {
+            path.push(level);
+            levels += 1;
+        }
+    }
+    (levels > 0).then_some(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_archive_kind_from_path() {
+        assert_eq!(
+            ArchiveKind::from_path(Path::new("a/b.TAR.GZ")),
+            Some(ArchiveKind::TarGz)
+        );
+        assert_eq!(
+            ArchiveKind::from_path(Path::new("b.tgz")),
+            Some(ArchiveKind::TarGz)
+        );
+        assert_eq!(
+            ArchiveKind::from_path(Path::new("b.tar")),
+            Some(ArchiveKind::Tar)
+        );
+        assert_eq!(
+            ArchiveKind::from_path(Path::new("b.zip")),
+            Some(ArchiveKind::Zip)
+        );
+        assert_eq!(ArchiveKind::from_path(Path::new("b.gz")), None);
+    }
+
+    #[test]
+    fn test_scan_tar_gz() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("backup.tar.gz");
+        let gz = flate2::write::GzEncoder::new(
+            File::create(&path).unwrap(),
+            flate2::Compression::default(),
+        );
+        let mut builder = tar::Builder::new(gz);
+        for (name, data) in [("usr/lib/a.so", &[0u8; 300][..]), ("usr/bin/b", &[1; 50])] {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(data.len() as u64);
+            header.set_cksum();
+            builder.append_data(&mut header, name, data).unwrap();
+        }
+        builder.into_inner().unwrap().finish().unwrap();
+
+        let scan = scan_archive(&path).unwrap();
+        let usr = scan.tree.lookup(&path.join("usr")).unwrap();
+        assert_eq!(scan.tree.size(usr), 350);
+        assert_eq!(scan.tree.file_count(scan.tree.root()), 2);
+        assert_eq!(scan.compressed.len(), 1);
+        assert_eq!(
+            scan.compressed[&path],
+            fs_len(&path),
+            "tar archives only report their size on disk"
+        );
+    }
+
+    #[test]
+    fn test_scan_zip_records_compressed_sizes() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("data.zip");
+        let mut writer = zip::ZipWriter::new(File::create(&path).unwrap());
+        let options = zip::write::SimpleFileOptions::default();
+        writer.add_directory("empty/", options).unwrap();
+        writer.start_file("docs/big.txt", options).unwrap();
+        writer.write_all(&[b'a'; 10_000]).unwrap();
+        writer.start_file("../escape.txt", options).unwrap();
+        writer.write_all(b"xyz").unwrap();
+        writer.finish().unwrap();
+
+        let scan = scan_archive(&path).unwrap();
+        let docs = path.join("docs");
+        let docs_id = scan.tree.lookup(&docs).unwrap();
+        assert_eq!(scan.tree.size(docs_id), 10_000);
+        assert!(scan.compressed[&docs] < 10_000);
+        // Parent components are stripped, keeping members inside the archive
+        assert_eq!(scan.tree.size(scan.tree.root()), 10_003);
+        assert!(scan.tree.lookup(&path.join("empty")).is_some());
+    }
+
+    #[test]
+    fn test_scan_wide_archives() {
+        // Many sibling directories used to make the tree build quadratic
+        const WIDTH: usize = 30_000;
+        let dir = tempdir().unwrap();
+
+        let tar_path = dir.path().join("wide.tar.gz");
+        let gz = flate2::write::GzEncoder::new(
+            File::create(&tar_path).unwrap(),
+            flate2::Compression::fast(),
+        );
+        let mut builder = tar::Builder::new(gz);
+        for i in 0..WIDTH {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(1);
+            header.set_cksum();
+            builder
+                .append_data(&mut header, format!("d{i}/file"), &b"x"[..])
+                .unwrap();
+        }
+        builder.into_inner().unwrap().finish().unwrap();
+
+        let zip_path = dir.path().join("wide.zip");
+        let mut writer = zip::ZipWriter::new(File::create(&zip_path).unwrap());
+        let options = zip::write::SimpleFileOptions::default()
+            .compression_method(zip::CompressionMethod::Stored);
+        for i in 0..WIDTH {
+            writer.start_file(format!("d{i}/file"), options).unwrap();
+            writer.write_all(b"x").unwrap();
+        }
+        writer.finish().unwrap();
+
+        for path in [tar_path, zip_path] {
+            let scan = scan_archive(&path).unwrap();
+            assert_eq!(scan.tree.len(), WIDTH + 1, "{}", path.display());
+            assert_eq!(scan.tree.size(scan.tree.root()), WIDTH as u64);
+            let last = scan.tree.lookup(&path.join(format!("d{}", WIDTH - 1)));
+            assert_eq!(scan.tree.file_count(last.unwrap()), 1);
+        }
+    }
+
+    fn fs_len(path: &Path) -> u64 {
+        std::fs::metadata(path).unwrap().len()
+    }
+}
@@ -0,0 +1,199 @@
+use crate::cancel::{CancellationToken, StopReason};
+use crate::error::{Error, Result};
+use crate::progress::ScanProgress;
+use crate::scanner::{ScanResult, Scanner};
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::thread;
+use tokio::sync::{mpsc, oneshot};
+
+/// Event reported by a scan started with [`Scanner::scan_async`].
+///
+/// Events carry running counts only. Sizes per directory are assembled once
+/// the walk is over; to get them early, call [`ScanHandle::cancel`] and the
+/// handle resolves to the partial result.
+#[derive(Clone, Debug)]
+#[non_exhaustive]
+pub enum ScanEvent {
+    /// Running totals, sent at most once per [`Scanner::progress_interval`]
+    Progress(ScanProgress),
+    /// The scan is over; the result is available from the [`ScanHandle`]
+    Finished {
+        /// Why the scan stopped early, or `None` if it completed
+        stopped: Option<StopReason>,
+        /// Directories and files that could not be read
+        errors: u64,
+    },
+}
+
+/// Future resolving to the result of a scan started with [`Scanner::scan_async`].
+///
+/// Resolves to [`Error::ScanFailed`] if the scan thread panicked or could not
+/// be started, or if it is polled again after returning the result. Dropping
+/// the handle before it resolves cancels the scan.
+pub struct ScanHandle {
+    result: oneshot::Receiver<ScanResult>,
+    cancel: CancellationToken,
+    done: bool,
+}
+
+impl ScanHandle {
+    /// Ask the scan to stop early; the handle then resolves to the partial result.
+    pub fn cancel(&self) {
+        self.cancel.cancel();
+    }
+}
+
+impl Future for ScanHandle {
+    type Output = Result<ScanResult>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        if self.done {
+            return Poll::Ready(Err(Error::ScanFailed));
+        }
+        let result = std::task::ready!(Pin::new(&mut self.result).poll(cx));
+        self.done = true;
+        // The sender is only dropped unsent if the scan thread went away
+        Poll::Ready(result.map_err(|_| Error::ScanFailed))
+    }
+}
+
+impl Drop for ScanHandle {
+    fn drop(&mut self) {
+        // Nobody is waiting for the result any more
+        if !self.done {
+            self.cancel.cancel();
+        }
+    }
+}
+
+impl Scanner<'static> {
+    /// Start the scan in the background and return immediately.
+    ///
+    /// The walk runs on the scanner's own thread pool, driven from a dedicated
+    /// thread, so no async runtime thread is ever blocked. Returns a future
+    /// resolving to the result, and a channel of [`ScanEvent`]s that ends
+    /// with [`ScanEvent::Finished`] unless the scan fails. The events report
+    /// progress counts, not partial sizes; see [`ScanEvent`]. Any callback
+    /// set with [`Scanner::on_progress`] is replaced.
+    ///
+    /// Requires the `async` cargo feature. The future and channel work with
+    /// any executor, although the channel comes from tokio.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use rudu::async_scan::ScanEvent;
+    /// use rudu::Scanner;
+    ///
+    /// # tokio::runtime::Builder::new_current_thread().build().unwrap().block_on(async {
+    /// let (handle, mut events) = Scanner::new("/tmp").scan_async();
+    /// while let Some(event) = events.recv().await {
+    ///     if let ScanEvent::Progress(progress) = event {
+    ///         println!("{} files so far", progress.files);
+    ///     }
+    /// }
+    /// let result = handle.await.unwrap();
+    /// println!("{} files in total", result.total_files());
+    /// # });
+    /// ```
+    pub fn scan_async(self) -> (ScanHandle, mpsc::UnboundedReceiver<ScanEvent>) {
+        let (events_tx, events_rx) = mpsc::unbounded_channel();
+        let (result_tx, result_rx) = oneshot::channel();
+
+        // Reuse the caller's token if any, so cancelling it still works
+        let cancel = self.cancel.clone().unwrap_or_default();
+        let progress_tx = events_tx.clone();
+        let scanner = self
+            .cancel_token(cancel.clone())
+            .on_progress(move |progress| {
+                let _ = progress_tx.send(ScanEvent::Progress(progress.clone()));
+            });
+
+        // If the thread cannot be started, dropping it drops the result
+        // sender and the handle resolves to an error
+        let _ = thread::Builder::new()
+            .name("rudu-async-scan".into())
+            .spawn(move || {
+                let result = scanner.scan();
+                // Drop the progress sender so the channel closes after `Finished`
+                drop(scanner);
+                let _ = events_tx.send(ScanEvent::Finished {
+                    stopped: result.stopped,
+                    errors: result.errors,
+                });
+                let _ = result_tx.send(result);
+            });
+
+        (
+            ScanHandle {
+                result: result_rx,
+                cancel,
+                done: false,
+            },
+            events_rx,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::filesystem::MemoryFs;
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn test_scan_async_reports_progress_and_result() {
+        let fs = MemoryFs::synthetic("/bench", 4, 3, 5, 10);
+        let (handle, mut events) = Scanner::new("/bench")
+            .filesystem(fs)
+            .progress_interval(Duration::from_millis(1))
+            .scan_async();
+
+        let mut received = Vec::new();
+        while let Some(event) = events.recv().await {
+            received.push(event);
+        }
+        let result = handle.await.unwrap();
+
+        // 1 + 4 + 16 + 64 directories with 5 files each
+        assert_eq!(result.total_files(), 85 * 5);
+        assert!(matches!(
+            received.last(),
+            Some(ScanEvent::Finished {
+                stopped: None,
+                errors: 0
+            })
+        ));
+        assert!(received
+            .iter()
+            .any(|event| matches!(event, ScanEvent::Progress(p) if p.files == 425)));
+    }
+
+    #[tokio::test]
+    async fn test_cancelled_async_scan() {
+        let token = CancellationToken::new();
+        token.cancel();
+        let (handle, _events) = Scanner::new("/").cancel_token(token).scan_async();
+        assert_eq!(handle.await.unwrap().stopped, Some(StopReason::Cancelled));
+    }
+
+    #[tokio::test]
+    async fn test_failed_async_scan_is_an_error() {
+        let mut fs = MemoryFs::new();
+        fs.add_file("/m/locked/file", 1);
+        fs.deny("/m/locked");
+        let (mut handle, mut events) = Scanner::new("/m")
+            .filesystem(fs)
+            .on_error(|_| panic!("error callback panicked"))
+            .scan_async();
+
+        assert!(matches!((&mut handle).await, Err(Error::ScanFailed)));
+        // Polling again after completion does not panic either
+        assert!(matches!((&mut handle).await, Err(Error::ScanFailed)));
+        while let Some(event) = events.recv().await {
+            assert!(!matches!(event, ScanEvent::Finished { .. }));
+        }
+    }
+}
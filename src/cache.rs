@@ -1,12 +1,20 @@
+use crate::error::{Error, Result};
+use crate::files_from::normalize_path;
+use crate::hardlinks::HardlinkStats;
+use crate::scanner::ScanOptions;
+use crate::symlinks::SymlinkStats;
 use dirs::cache_dir;
+use fs4::fs_std::FileExt;
 use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
 use std::collections::HashMap;
-use std::fs;
+use std::fs::{self, File, OpenOptions};
+use std::io::Write;
 use std::path::{Path, PathBuf};
-use std::time::{SystemTime, UNIX_EPOCH};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
 /// Cache entry containing directory scan results
-#[derive(Serialize, Deserialize, Clone)]
+#[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct CacheEntry {
     /// Directory sizes mapping
     pub sizes: HashMap<PathBuf, u64>,
@@ -16,57 +24,456 @@ pub struct CacheEntry {
     pub timestamp: u64,
     /// Base directory that was scanned
     pub base_path: PathBuf,
+    /// Multiply-linked inodes counted in this scan, as `[device, inode, size]`
+    #[serde(default)]
+    pub hardlinks: Vec<[u64; 3]>,
+    /// Directories holding the links counted for those inodes, as
+    /// `(directory, [device, inode, bytes counted there])`; empty in older
+    /// entries
+    #[serde(default)]
+    pub hardlink_dirs: Vec<(PathBuf, [u64; 3])>,
+    /// Scanner settings the sizes were computed with
+    #[serde(default)]
+    pub options: ScanOptions,
+    /// Files reached through more than one hard link
+    #[serde(default)]
+    pub hardlink_stats: HardlinkStats,
+    /// Symlinks followed, broken and looping
+    #[serde(default)]
+    pub symlinks: SymlinkStats,
+    /// Number of files in each directory's subtree; empty in older entries
+    #[serde(default)]
+    pub file_counts: HashMap<PathBuf, u64>,
+    /// Newest file modification time in each directory's subtree, in seconds
+    /// since the Unix epoch; empty in older entries
+    #[serde(default)]
+    pub modified: HashMap<PathBuf, u64>,
+}
+
+impl CacheEntry {
+    /// An entry for a scan of `base_path` finished now, without hardlink information.
+    pub fn new(
+        base_path: &Path,
+        sizes: HashMap<PathBuf, u64>,
+        total_files: usize,
+        options: ScanOptions,
+    ) -> Self {
+        CacheEntry {
+            sizes,
+            total_files,
+            timestamp: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_secs(),
+            base_path: base_path.to_path_buf(),
+            hardlinks: Vec::new(),
+            hardlink_dirs: Vec::new(),
+            options,
+            hardlink_stats: HardlinkStats::default(),
+            symlinks: SymlinkStats::default(),
+            file_counts: HashMap::new(),
+            modified: HashMap::new(),
+        }
+    }
+
+    /// Set the hardlinked inodes counted, from a `(device, inode) -> size` map.
+    pub fn with_hardlinks(mut self, hardlinks: &HashMap<(u64, u64), u64>) -> Self {
+        self.hardlinks = hardlinks
+            .iter()
+            .map(|(&(dev, ino), &size)| [dev, ino, size])
+            .collect();
+        self
+    }
+
+    /// Set the directories holding the links counted for each hardlinked
+    /// inode, with the bytes counted in each.
+    pub fn with_hardlink_dirs(
+        mut self,
+        hardlink_dirs: &HashMap<(u64, u64), Vec<(PathBuf, u64)>>,
+    ) -> Self {
+        self.hardlink_dirs = hardlink_dirs
+            .iter()
+            .flat_map(|(&(dev, ino), dirs)| {
+                dirs.iter()
+                    .map(move |(dir, bytes)| (dir.clone(), [dev, ino, *bytes]))
+            })
+            .collect();
+        self
+    }
+
+    /// Set the number of files in each directory's subtree.
+    pub fn with_file_counts(mut self, file_counts: HashMap<PathBuf, u64>) -> Self {
+        self.file_counts = file_counts;
+        self
+    }
+
+    /// Set the newest modification time in each directory's subtree.
+    pub fn with_modified(mut self, modified: HashMap<PathBuf, u64>) -> Self {
+        self.modified = modified;
+        self
+    }
+
+    /// Hardlinked inodes as a `(device, inode) -> size` map
+    pub fn hardlink_map(&self) -> HashMap<(u64, u64), u64> {
+        self.hardlinks
+            .iter()
+            .map(|&[dev, ino, size]| ((dev, ino), size))
+            .collect()
+    }
+
+    /// Directories holding the links counted for each hardlinked inode, as
+    /// a `(device, inode) -> [(directory, bytes)]` map, keeping only
+    /// directories under `dir`
+    pub fn hardlink_dirs_below(&self, dir: &Path) -> HashMap<(u64, u64), Vec<(PathBuf, u64)>> {
+        let mut map: HashMap<(u64, u64), Vec<(PathBuf, u64)>> = HashMap::new();
+        for (path, [dev, ino, bytes]) in &self.hardlink_dirs {
+            if path.starts_with(dir) {
+                map.entry((*dev, *ino))
+                    .or_default()
+                    .push((path.clone(), *bytes));
+            }
+        }
+        map
+    }
+
+    /// Time since the scan was stored.
+    pub fn age(&self) -> Duration {
+        age_of(self.timestamp)
+    }
+}
+
+/// Time elapsed since `timestamp`, in seconds since the Unix epoch.
+fn age_of(timestamp: u64) -> Duration {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+    Duration::from_secs(now.saturating_sub(timestamp))
+}
+
+/// Environment variable overriding the default cache directory.
+pub const CACHE_DIR_ENV: &str = "RUDU_CACHE_DIR";
+
+/// Lock file serializing writers to a cache directory.
+const LOCK_FILE: &str = "cache.lock";
+
+/// Version of the cache key derivation, prefixed to entry file names.
+///
+/// Bump this whenever [`Cache::cache_key`] or [`ScanOptions`] changes, so
+/// that existing entries are moved to their new keys when a cache is opened.
+const KEY_VERSION: &str = "v4";
+
+/// Subdirectory where unreadable cache entries are moved aside.
+const QUARANTINE_DIR: &str = "quarantine";
+
+/// Extension of the header file stored next to each entry, which holds the
+/// fields of an [`EntryHeader`] so that entries can be listed and matched
+/// without parsing their size maps.
+const HEADER_EXTENSION: &str = "head";
+
+/// Default limit on the total size of cache entries: 500 MB.
+pub const DEFAULT_MAX_BYTES: u64 = 500_000_000;
+
+/// Default limit on the number of cache entries.
+pub const DEFAULT_MAX_ENTRIES: usize = 1000;
+
+/// What [`Cache::prune`] removed.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct PruneStats {
+    /// Entries older than the maximum age
+    pub expired: usize,
+    /// Entries whose scanned directory no longer exists
+    pub orphaned: usize,
+    /// Unreadable entries, including previously quarantined ones
+    pub corrupt: usize,
+    /// Least recently used entries removed to get back within the limits
+    pub evicted: usize,
+    /// Bytes freed in total
+    pub freed_bytes: u64,
+}
+
+impl PruneStats {
+    /// Number of entries removed.
+    pub fn removed(&self) -> usize {
+        self.expired + self.orphaned + self.corrupt + self.evicted
+    }
+}
+
+/// Summary of a cache entry, as returned by [`Cache::list`].
+#[derive(Clone, Debug)]
+pub struct EntryInfo {
+    /// Directory that was scanned
+    pub base_path: PathBuf,
+    /// When the scan was stored, in seconds since the Unix epoch
+    pub timestamp: u64,
+    /// Number of files counted by the scan
+    pub total_files: usize,
+    /// Scanner settings the sizes were computed with
+    pub options: ScanOptions,
+    /// Size of the entry on disk in bytes, including its header file
+    pub size: u64,
+}
+
+impl EntryInfo {
+    /// Time since the scan was stored.
+    pub fn age(&self) -> Duration {
+        age_of(self.timestamp)
+    }
+}
+
+/// The fields of a [`CacheEntry`] needed to list it, skipping the size map.
+#[derive(Serialize, Deserialize)]
+struct EntryHeader {
+    total_files: usize,
+    timestamp: u64,
+    base_path: PathBuf,
+    #[serde(default)]
+    options: ScanOptions,
+}
+
+impl EntryHeader {
+    fn of(entry: &CacheEntry) -> Self {
+        EntryHeader {
+            total_files: entry.total_files,
+            timestamp: entry.timestamp,
+            base_path: entry.base_path.clone(),
+            options: entry.options,
+        }
+    }
+}
+
+/// An entry file in the cache directory.
+struct EntryFile {
+    path: PathBuf,
+    /// Size of the entry file and its header file together
+    len: u64,
+    /// Last store or successful retrieve
+    used: SystemTime,
+}
+
+/// How the cache was used for a scan, as reported in [`DirSizes::cache`](crate::DirSizes::cache).
+#[derive(Debug)]
+pub enum CacheStatus {
+    /// Caching was not requested
+    Disabled,
+    /// Served from a cached scan of the same directory
+    Hit,
+    /// Served from a cached scan of the parent directory
+    ParentHit,
+    /// Scanned and written to the cache
+    Stored,
+    /// Combined from this many cached scans of subdirectories and a walk of
+    /// the rest, and written to the cache
+    Composed(usize),
+    /// Scanned but not cached, because the scan did not complete
+    Incomplete,
+    /// Scanned, but the cache could not be read or written
+    Failed(Error),
 }
 
 /// Cache manager for directory scan results
+///
+/// The cache is kept within a maximum total size and number of entries. When
+/// a store exceeds either limit, the least recently used entries are evicted.
 pub struct Cache {
     cache_dir: PathBuf,
+    max_bytes: u64,
+    max_entries: usize,
 }
 
 impl Cache {
-    /// Create a new cache instance
-    pub fn new() -> Result<Self, Box<dyn std::error::Error>> {
-        let cache_dir = cache_dir()
-            .ok_or("Could not determine cache directory")?
-            .join("rudu");
+    /// Create a cache in the directory named by `RUDU_CACHE_DIR`, or in
+    /// `rudu` under the user's cache directory if it is unset.
+    ///
+    /// Fails if there is no user cache directory or the directory cannot be
+    /// created, e.g. under a read-only home directory.
+    pub fn new() -> Result<Self> {
+        match std::env::var_os(CACHE_DIR_ENV).filter(|dir| !dir.is_empty()) {
+            Some(dir) => Self::with_dir(dir),
+            None => Self::with_dir(cache_dir().ok_or(Error::NoCacheDir)?.join("rudu")),
+        }
+    }
+
+    /// Create a cache in `cache_dir`, creating the directory if needed.
+    ///
+    /// Any directory works, including one in a CI workspace, a volume
+    /// mounted into a container or a network share used by several hosts.
+    pub fn with_dir(cache_dir: impl Into<PathBuf>) -> Result<Self> {
+        let cache_dir = cache_dir.into();
+        fs::create_dir_all(&cache_dir).map_err(|e| Error::cache_io(&cache_dir, e))?;
+        let cache = Cache {
+            cache_dir,
+            max_bytes: DEFAULT_MAX_BYTES,
+            max_entries: DEFAULT_MAX_ENTRIES,
+        };
+        // Entries that cannot be migrated are simply rescanned later
+        let _ = cache.migrate();
+        Ok(cache)
+    }
 
-        // Create cache directory if it doesn't exist
-        fs::create_dir_all(&cache_dir)?;
+    /// Limit the total size of cache entries to `bytes` (default: [`DEFAULT_MAX_BYTES`]).
+    pub fn max_size(mut self, bytes: u64) -> Self {
+        self.max_bytes = bytes;
+        self
+    }
+
+    /// Limit the number of cache entries (default: [`DEFAULT_MAX_ENTRIES`]).
+    pub fn max_entries(mut self, entries: usize) -> Self {
+        self.max_entries = entries;
+        self
+    }
+
+    /// Generate the cache key for scanning `path` with `options`.
+    ///
+    /// The key is the hex SHA-256 digest of [`KEY_VERSION`], the canonical
+    /// path's bytes and the options serialized as JSON, separated by NUL
+    /// bytes. It is the same for every build and platform of the same key
+    /// version. Paths that cannot be canonicalized, e.g. because they no
+    /// longer exist, are normalized lexically instead.
+    fn cache_key(&self, path: &Path, options: &ScanOptions) -> String {
+        let path = path.canonicalize().unwrap_or_else(|_| normalize_path(path));
+        #[cfg(unix)]
+        let path_bytes = {
+            use std::os::unix::ffi::OsStrExt;
+            path.as_os_str().as_bytes().to_vec()
+        };
+        #[cfg(not(unix))]
+        let path_bytes = path.to_string_lossy().into_owned().into_bytes();
 
-        Ok(Cache { cache_dir })
+        let mut hasher = Sha256::new();
+        hasher.update(KEY_VERSION.as_bytes());
+        hasher.update([0]);
+        hasher.update(&path_bytes);
+        hasher.update([0]);
+        hasher.update(
+            serde_json::to_string(options)
+                .unwrap_or_default()
+                .as_bytes(),
+        );
+        format!("{:x}", hasher.finalize())
     }
 
-    /// Create a cache instance with a custom directory (for testing)
-    #[cfg(test)]
-    pub fn with_dir(cache_dir: PathBuf) -> Result<Self, Box<dyn std::error::Error>> {
-        fs::create_dir_all(&cache_dir)?;
-        Ok(Cache { cache_dir })
+    /// Get cache file path for scanning a given directory with `options`
+    fn cache_file_path(&self, path: &Path, options: &ScanOptions) -> PathBuf {
+        self.cache_dir.join(format!(
+            "{KEY_VERSION}-{}.json",
+            self.cache_key(path, options)
+        ))
     }
 
-    /// Generate a cache key for a given path
-    fn cache_key(&self, path: &Path) -> String {
-        use ahash::AHasher;
-        use std::hash::{Hash, Hasher};
+    /// Move entries stored under an older key scheme to their current keys,
+    /// returning how many were moved, and write the header files of entries
+    /// stored before there were any.
+    ///
+    /// Runs when the cache is opened. Unreadable entries are quarantined,
+    /// and an entry whose current key is already taken is dropped.
+    fn migrate(&self) -> Result<usize> {
+        let prefix = format!("{KEY_VERSION}-");
+        let (current, outdated): (Vec<EntryFile>, Vec<EntryFile>) =
+            self.entry_files()?.into_iter().partition(|file| {
+                file.path
+                    .file_name()
+                    .and_then(|name| name.to_str())
+                    .is_some_and(|name| name.starts_with(&prefix))
+            });
+        let headless: Vec<EntryFile> = current
+            .into_iter()
+            .filter(|file| !header_path(&file.path).exists())
+            .collect();
+        if outdated.is_empty() && headless.is_empty() {
+            return Ok(0);
+        }
 
-        let mut hasher = AHasher::default();
-        path.hash(&mut hasher);
-        format!("{:x}", hasher.finish())
+        let _lock = self.lock()?;
+        let mut migrated = 0;
+        for file in outdated {
+            let header = match read_header(&file.path) {
+                Some(header) => header,
+                None => {
+                    if file.path.exists() {
+                        let _ = self.quarantine_locked(&file.path);
+                    }
+                    continue;
+                }
+            };
+            let target = self.cache_file_path(&header.base_path, &header.options);
+            let moved = if target.exists() {
+                remove_entry_file(&file.path)
+            } else {
+                let _ = fs::remove_file(header_path(&file.path));
+                fs::rename(&file.path, &target).map(|()| migrated += 1)
+            };
+            match moved {
+                Err(e) if e.kind() != std::io::ErrorKind::NotFound => {
+                    return Err(Error::cache_io(&file.path, e))
+                }
+                _ => {}
+            }
+            if !header_path(&target).exists() {
+                if let Some(header) = read_header(&target) {
+                    self.write_header(&target, &header)?;
+                }
+            }
+        }
+        for file in headless {
+            // Unreadable entries are quarantined when they are next read
+            if let Some(header) = read_header(&file.path) {
+                self.write_header(&file.path, &header)?;
+            }
+        }
+        Ok(migrated)
     }
 
-    /// Get cache file path for a given directory
-    fn cache_file_path(&self, path: &Path) -> PathBuf {
-        self.cache_dir
-            .join(format!("{}.json", self.cache_key(path)))
+    /// Take the cache-wide advisory write lock, held until the returned file
+    /// is dropped.
+    ///
+    /// Entries are replaced atomically, so readers only take the lock to
+    /// remove an expired entry or quarantine an unreadable one.
+    fn lock(&self) -> Result<File> {
+        let path = self.cache_dir.join(LOCK_FILE);
+        let file = OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(&path)
+            .map_err(|e| Error::cache_io(&path, e))?;
+        FileExt::lock_exclusive(&file).map_err(|e| Error::cache_io(&path, e))?;
+        Ok(file)
     }
 
-    /// Check if cache entry is valid (not too old)
-    fn is_cache_valid(&self, entry: &CacheEntry, max_age_seconds: u64) -> bool {
-        let current_time = SystemTime::now()
+    /// Move an unreadable entry into the quarantine directory so that it is
+    /// rescanned next time, while keeping it around for inspection. The
+    /// caller must hold the write lock.
+    fn quarantine_locked(&self, cache_file: &Path) -> Result<()> {
+        let dir = self.cache_dir.join(QUARANTINE_DIR);
+        fs::create_dir_all(&dir).map_err(|e| Error::cache_io(&dir, e))?;
+
+        let name = cache_file.file_name().unwrap_or_default().to_string_lossy();
+        let now = SystemTime::now()
             .duration_since(UNIX_EPOCH)
-            .unwrap()
+            .unwrap_or_default()
             .as_secs();
+        let target = dir.join(format!("{name}.{now}"));
+        let _ = fs::remove_file(header_path(cache_file));
+        match fs::rename(cache_file, &target) {
+            // Another process got there first
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(()),
+            result => result.map_err(|e| Error::cache_io(cache_file, e)),
+        }
+    }
 
-        current_time - entry.timestamp <= max_age_seconds
+    /// Check if cache entry is valid (not too old)
+    fn is_cache_valid(&self, entry: &CacheEntry, max_age_seconds: u64) -> bool {
+        entry.age().as_secs() <= max_age_seconds
+    }
+
+    /// Record a use of `cache_file` in its modification time, for LRU eviction.
+    ///
+    /// Failing to update it, e.g. on a read-only cache, is harmless.
+    fn touch(&self, cache_file: &Path) {
+        if let Ok(file) = OpenOptions::new().write(true).open(cache_file) {
+            let _ = file.set_modified(SystemTime::now());
+        }
     }
 
     /// Store scan results in cache
@@ -75,50 +482,321 @@ impl Cache {
         path: &Path,
         sizes: &HashMap<PathBuf, u64>,
         total_files: usize,
-    ) -> Result<(), Box<dyn std::error::Error>> {
-        let timestamp = SystemTime::now()
-            .duration_since(UNIX_EPOCH)
-            .unwrap()
-            .as_secs();
+    ) -> Result<()> {
+        self.store_with_hardlinks(path, sizes, total_files, &HashMap::new())
+    }
 
-        let entry = CacheEntry {
-            sizes: sizes.clone(),
-            total_files,
-            timestamp,
-            base_path: path.to_path_buf(),
-        };
+    /// Store scan results in cache along with the hardlinked inodes they counted
+    pub fn store_with_hardlinks(
+        &self,
+        path: &Path,
+        sizes: &HashMap<PathBuf, u64>,
+        total_files: usize,
+        hardlinks: &HashMap<(u64, u64), u64>,
+    ) -> Result<()> {
+        let options = ScanOptions::default();
+        self.store_with_options(path, sizes, total_files, hardlinks, &options)
+    }
 
-        let cache_file = self.cache_file_path(path);
-        let json = serde_json::to_string_pretty(&entry)?;
-        fs::write(cache_file, json)?;
+    /// Store scan results in cache along with the hardlinked inodes they
+    /// counted and the scanner settings they were computed with.
+    ///
+    /// Results for the same path with different options are cached separately.
+    pub fn store_with_options(
+        &self,
+        path: &Path,
+        sizes: &HashMap<PathBuf, u64>,
+        total_files: usize,
+        hardlinks: &HashMap<(u64, u64), u64>,
+        options: &ScanOptions,
+    ) -> Result<()> {
+        let entry =
+            CacheEntry::new(path, sizes.clone(), total_files, *options).with_hardlinks(hardlinks);
+        self.store_entry(&entry)
+    }
 
+    /// Store a complete entry, keyed by its base path and options.
+    pub fn store_entry(&self, entry: &CacheEntry) -> Result<()> {
+        let cache_file = self.cache_file_path(&entry.base_path, &entry.options);
+        let json = serde_json::to_string_pretty(entry)
+            .map_err(|e| Error::cache_io(&cache_file, e.into()))?;
+
+        let _lock = self.lock()?;
+        self.write_atomically(&cache_file, json.as_bytes())?;
+        self.write_header(&cache_file, &EntryHeader::of(entry))?;
+        self.evict(&cache_file)?;
+        Ok(())
+    }
+
+    /// Write `contents` to a complete temporary file, then rename it over
+    /// `target`, so readers and crashes never see a partially written file.
+    /// The caller must hold the write lock.
+    fn write_atomically(&self, target: &Path, contents: &[u8]) -> Result<()> {
+        let io_error = |e| Error::cache_io(target, e);
+        let mut temp = tempfile::NamedTempFile::new_in(&self.cache_dir).map_err(io_error)?;
+        temp.write_all(contents).map_err(io_error)?;
+        temp.as_file().sync_all().map_err(io_error)?;
+        temp.persist(target).map_err(|e| io_error(e.error))?;
+        Ok(())
+    }
+
+    /// Write the header file of the entry in `cache_file`. The caller must
+    /// hold the write lock.
+    fn write_header(&self, cache_file: &Path, header: &EntryHeader) -> Result<()> {
+        let path = header_path(cache_file);
+        let json = serde_json::to_string(header).map_err(|e| Error::cache_io(&path, e.into()))?;
+        self.write_atomically(&path, json.as_bytes())
+    }
+
+    /// Remove the least recently used entries, other than `keep`, until the
+    /// cache is within its limits. The caller must hold the write lock.
+    fn evict(&self, keep: &Path) -> Result<(usize, u64)> {
+        let mut entries = self.entry_files()?;
+        let mut count = entries.len();
+        let mut total: u64 = entries.iter().map(|entry| entry.len).sum();
+        entries.sort_by_key(|entry| entry.used);
+
+        let (mut evicted, mut freed) = (0, 0);
+        for entry in entries {
+            if count <= self.max_entries && total <= self.max_bytes {
+                break;
+            }
+            if entry.path == keep {
+                continue;
+            }
+            match remove_entry_file(&entry.path) {
+                Err(e) if e.kind() != std::io::ErrorKind::NotFound => {
+                    return Err(Error::cache_io(&entry.path, e))
+                }
+                _ => {}
+            }
+            count -= 1;
+            total -= entry.len;
+            evicted += 1;
+            freed += entry.len;
+        }
+        Ok((evicted, freed))
+    }
+
+    /// The entry files in the cache directory, skipping the lock file,
+    /// header files, temporary files and quarantined entries.
+    fn entry_files(&self) -> Result<Vec<EntryFile>> {
+        let io_error = |e| Error::cache_io(&self.cache_dir, e);
+        let mut files = Vec::new();
+        if !self.cache_dir.exists() {
+            return Ok(files);
+        }
+
+        for entry in fs::read_dir(&self.cache_dir).map_err(io_error)? {
+            let entry = entry.map_err(io_error)?;
+            let path = entry.path();
+            if path.extension().and_then(|s| s.to_str()) != Some("json") {
+                continue;
+            }
+            // Entries can disappear under a concurrent retrieve of an expired entry
+            let Ok(metadata) = entry.metadata() else {
+                continue;
+            };
+            let header_len = fs::metadata(header_path(&path)).map_or(0, |header| header.len());
+            files.push(EntryFile {
+                len: metadata.len() + header_len,
+                used: metadata.modified().unwrap_or(UNIX_EPOCH),
+                path,
+            });
+        }
+        Ok(files)
+    }
+
+    /// Read the entry in `cache_file`, quarantining it if it cannot be parsed.
+    fn read_entry(&self, cache_file: &Path) -> Result<Option<CacheEntry>> {
+        if let Some(Ok(entry)) = parse_entry_file(cache_file)? {
+            return Ok(Some(entry));
+        }
+        // A concurrent store may have replaced the entry since it was read,
+        // so read it again under the lock before moving it aside
+        let _lock = self.lock()?;
+        match parse_entry_file(cache_file)? {
+            None => Ok(None),
+            Some(Ok(entry)) => Ok(Some(entry)),
+            Some(Err(source)) => {
+                self.quarantine_locked(cache_file)?;
+                Err(Error::CorruptCache {
+                    path: cache_file.to_path_buf(),
+                    source,
+                })
+            }
+        }
+    }
+
+    /// Remove the entry in `cache_file` if it still holds the scan stored at
+    /// `timestamp`, and not one that a concurrent store replaced it with.
+    fn remove_expired(&self, cache_file: &Path, timestamp: u64) -> Result<()> {
+        let _lock = self.lock()?;
+        if read_stored_header(cache_file).is_some_and(|header| header.timestamp == timestamp) {
+            remove_entry_file(cache_file).map_err(|e| Error::cache_io(cache_file, e))?;
+        }
         Ok(())
     }
 
     /// Retrieve scan results from cache if available and valid
-    pub fn retrieve(
+    pub fn retrieve(&self, path: &Path, max_age_seconds: u64) -> Result<Option<CacheEntry>> {
+        self.retrieve_with_options(path, &ScanOptions::default(), max_age_seconds)
+    }
+
+    /// Retrieve scan results made with `options` from cache if available and valid
+    pub fn retrieve_with_options(
         &self,
         path: &Path,
+        options: &ScanOptions,
         max_age_seconds: u64,
-    ) -> Result<Option<CacheEntry>, Box<dyn std::error::Error>> {
-        let cache_file = self.cache_file_path(path);
-
-        if !cache_file.exists() {
-            return Ok(None);
-        }
-
-        let json = fs::read_to_string(cache_file)?;
-        let entry: CacheEntry = serde_json::from_str(&json)?;
+    ) -> Result<Option<CacheEntry>> {
+        let cache_file = self.cache_file_path(path, options);
+        let entry = match self.read_entry(&cache_file)? {
+            // Sizes are keyed by the scanned path, so an entry for another
+            // spelling of the same directory (e.g. through a symlink) is a miss
+            Some(entry) if entry.base_path == path && entry.options == *options => entry,
+            _ => return Ok(None),
+        };
 
         if self.is_cache_valid(&entry, max_age_seconds) {
+            self.touch(&cache_file);
             Ok(Some(entry))
         } else {
             // Cache is too old, remove it
-            let _ = fs::remove_file(self.cache_file_path(path));
+            let _ = self.remove_expired(&cache_file, entry.timestamp);
             Ok(None)
         }
     }
 
+    /// Retrieve every valid cached scan of a directory strictly below `path`
+    /// made with default options.
+    pub fn retrieve_below(&self, path: &Path, max_age_seconds: u64) -> Result<Vec<CacheEntry>> {
+        self.retrieve_below_with_options(path, &ScanOptions::default(), max_age_seconds)
+    }
+
+    /// Retrieve every valid cached scan of a directory strictly below `path`
+    /// made with `options`.
+    ///
+    /// Candidates are found from the entries' header files, so only the
+    /// matching entries are read in full.
+    pub fn retrieve_below_with_options(
+        &self,
+        path: &Path,
+        options: &ScanOptions,
+        max_age_seconds: u64,
+    ) -> Result<Vec<CacheEntry>> {
+        let is_below = |base: &Path, timestamp: u64, entry_options: &ScanOptions| {
+            base != path
+                && base.starts_with(path)
+                && entry_options == options
+                && age_of(timestamp).as_secs() <= max_age_seconds
+        };
+        let mut entries = Vec::new();
+        for file in self.entry_files()? {
+            let below = read_header(&file.path).is_some_and(|header| {
+                is_below(&header.base_path, header.timestamp, &header.options)
+            });
+            if !below {
+                continue;
+            }
+            // Corrupt entries are quarantined and skipped like missing ones,
+            // and an entry replaced since its header was read is checked again
+            match self.read_entry(&file.path) {
+                Ok(Some(entry)) if is_below(&entry.base_path, entry.timestamp, &entry.options) => {
+                    self.touch(&file.path);
+                    entries.push(entry);
+                }
+                _ => {}
+            }
+        }
+        Ok(entries)
+    }
+
+    /// Read the cached scan of `path` regardless of its age, without marking
+    /// it as used.
+    ///
+    /// Prefers the scan made with default options, then the newest one.
+    pub fn get(&self, path: &Path) -> Result<Option<CacheEntry>> {
+        if let Some(entry) = self.get_with_options(path, &ScanOptions::default())? {
+            return Ok(Some(entry));
+        }
+
+        // Scans with other options have other keys
+        let newest = self
+            .entry_files()?
+            .into_iter()
+            .filter_map(|file| {
+                let header = read_header(&file.path)?;
+                (header.base_path == path).then_some((header.timestamp, file.path))
+            })
+            .max();
+        match newest {
+            Some((_, cache_file)) => self.read_entry(&cache_file),
+            None => Ok(None),
+        }
+    }
+
+    /// Read the scan of `path` made with `options` regardless of its age,
+    /// without marking it as used.
+    pub fn get_with_options(
+        &self,
+        path: &Path,
+        options: &ScanOptions,
+    ) -> Result<Option<CacheEntry>> {
+        let cache_file = self.cache_file_path(path, options);
+        Ok(self
+            .read_entry(&cache_file)?
+            .filter(|entry| entry.base_path == path && entry.options == *options))
+    }
+
+    /// Summaries of all readable cache entries, sorted by base path.
+    pub fn list(&self) -> Result<Vec<EntryInfo>> {
+        let mut entries: Vec<EntryInfo> = self
+            .entry_files()?
+            .into_iter()
+            .filter_map(|file| {
+                let header = read_header(&file.path)?;
+                Some(EntryInfo {
+                    base_path: header.base_path,
+                    timestamp: header.timestamp,
+                    total_files: header.total_files,
+                    options: header.options,
+                    size: file.len,
+                })
+            })
+            .collect();
+        entries.sort_by(|a, b| a.base_path.cmp(&b.base_path));
+        Ok(entries)
+    }
+
+    /// Remove the cached scan of `path`, returning whether there was one.
+    pub fn invalidate(&self, path: &Path) -> Result<bool> {
+        Ok(self.remove_where(|base| base == path)? > 0)
+    }
+
+    /// Remove the cached scans of `prefix` and every directory below it,
+    /// returning how many were removed.
+    pub fn invalidate_under(&self, prefix: &Path) -> Result<usize> {
+        self.remove_where(|base| base.starts_with(prefix))
+    }
+
+    /// Remove every entry whose base path matches `matches`.
+    fn remove_where(&self, matches: impl Fn(&Path) -> bool) -> Result<usize> {
+        let _lock = self.lock()?;
+        let mut removed = 0;
+        for file in self.entry_files()? {
+            if !read_header(&file.path).is_some_and(|header| matches(&header.base_path)) {
+                continue;
+            }
+            match remove_entry_file(&file.path) {
+                Ok(()) => removed += 1,
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+                Err(e) => return Err(Error::cache_io(&file.path, e)),
+            }
+        }
+        Ok(removed)
+    }
+
     /// Check if we can use cached data for a subdirectory scan
     pub fn can_use_for_subdir(
         &self,
@@ -138,11 +816,13 @@ impl Cache {
             if path.starts_with(subdir) {
                 filtered_sizes.insert(path.clone(), *size);
 
-                // Count files in this directory (approximate)
+                // Count files in this directory, estimating it for entries
+                // stored before per-directory counts were
                 if path == subdir {
-                    // This is a rough estimate - in a real implementation,
-                    // we'd need to store file counts per directory
-                    file_count = (*size / 1024).max(1) as usize; // Rough estimate
+                    file_count = match parent_cache.file_counts.get(path) {
+                        Some(count) => *count as usize,
+                        None => (*size / 1024).max(1) as usize,
+                    };
                 }
             }
         }
@@ -154,41 +834,333 @@ impl Cache {
         }
     }
 
-    /// Clear all cache entries
-    pub fn clear(&self) -> Result<(), Box<dyn std::error::Error>> {
-        if self.cache_dir.exists() {
-            fs::remove_dir_all(&self.cache_dir)?;
-            fs::create_dir_all(&self.cache_dir)?;
+    /// Clear all cache entries, including quarantined ones
+    pub fn clear(&self) -> Result<()> {
+        if !self.cache_dir.exists() {
+            return Ok(());
+        }
+
+        let _lock = self.lock()?;
+        let io_error = |e| Error::cache_io(&self.cache_dir, e);
+        for entry in fs::read_dir(&self.cache_dir).map_err(io_error)? {
+            let path = entry.map_err(io_error)?.path();
+            if path.file_name() == Some(LOCK_FILE.as_ref()) {
+                continue;
+            }
+            let removed = if path.is_dir() {
+                fs::remove_dir_all(&path)
+            } else {
+                fs::remove_file(&path)
+            };
+            removed.map_err(|e| Error::cache_io(&path, e))?;
         }
         Ok(())
     }
 
+    /// Remove expired entries, entries whose scanned directory no longer
+    /// exists and unreadable entries, then evict least recently used entries
+    /// until the cache is within its limits.
+    pub fn prune(&self, max_age_seconds: u64) -> Result<PruneStats> {
+        let mut stats = PruneStats::default();
+        let _lock = self.lock()?;
+
+        for file in self.entry_files()? {
+            let entry = fs::read_to_string(&file.path)
+                .ok()
+                .and_then(|json| serde_json::from_str::<CacheEntry>(&json).ok());
+            let counter = match &entry {
+                None => &mut stats.corrupt,
+                Some(entry) if !self.is_cache_valid(entry, max_age_seconds) => &mut stats.expired,
+                Some(entry) if !entry.base_path.exists() => &mut stats.orphaned,
+                Some(_) => continue,
+            };
+            match remove_entry_file(&file.path) {
+                Ok(()) => {
+                    *counter += 1;
+                    stats.freed_bytes += file.len;
+                }
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+                Err(e) => return Err(Error::cache_io(&file.path, e)),
+            }
+        }
+
+        // Header files left behind by an entry removed without them
+        if let Ok(entries) = fs::read_dir(&self.cache_dir) {
+            for entry in entries.filter_map(|entry| entry.ok()) {
+                let path = entry.path();
+                if path.extension().and_then(|s| s.to_str()) != Some(HEADER_EXTENSION)
+                    || path.with_extension("json").exists()
+                {
+                    continue;
+                }
+                let len = entry.metadata().map_or(0, |metadata| metadata.len());
+                if fs::remove_file(&path).is_ok() {
+                    stats.freed_bytes += len;
+                }
+            }
+        }
+
+        let quarantine = self.cache_dir.join(QUARANTINE_DIR);
+        if let Ok(entries) = fs::read_dir(&quarantine) {
+            for entry in entries.filter_map(|entry| entry.ok()) {
+                let len = entry.metadata().map_or(0, |metadata| metadata.len());
+                if fs::remove_file(entry.path()).is_ok() {
+                    stats.corrupt += 1;
+                    stats.freed_bytes += len;
+                }
+            }
+        }
+
+        let (evicted, freed) = self.evict(Path::new(""))?;
+        stats.evicted = evicted;
+        stats.freed_bytes += freed;
+        Ok(stats)
+    }
+
     /// Get cache directory path
     pub fn cache_directory(&self) -> &Path {
         &self.cache_dir
     }
 
     /// Get cache statistics
-    pub fn stats(&self) -> Result<(usize, u64), Box<dyn std::error::Error>> {
-        let mut count = 0;
-        let mut total_size = 0;
-
-        if self.cache_dir.exists() {
-            for entry in fs::read_dir(&self.cache_dir)? {
-                let entry = entry?;
-                if entry.path().extension().and_then(|s| s.to_str()) == Some("json") {
-                    count += 1;
-                    total_size += entry.metadata()?.len();
-                }
-            }
-        }
+    pub fn stats(&self) -> Result<(usize, u64)> {
+        let entries = self.entry_files()?;
+        Ok((entries.len(), entries.iter().map(|entry| entry.len).sum()))
+    }
+}
 
-        Ok((count, total_size))
+/// The header file of the entry in `cache_file`.
+fn header_path(cache_file: &Path) -> PathBuf {
+    cache_file.with_extension(HEADER_EXTENSION)
+}
+
+/// Remove the entry in `cache_file` along with its header file.
+fn remove_entry_file(cache_file: &Path) -> std::io::Result<()> {
+    let removed = fs::remove_file(cache_file);
+    let _ = fs::remove_file(header_path(cache_file));
+    removed
+}
+
+/// Read the summary fields of the entry in `cache_file`, or `None` if it is
+/// missing or unreadable.
+///
+/// They are read from the entry's header file, falling back to parsing the
+/// whole entry if there is none.
+fn read_header(cache_file: &Path) -> Option<EntryHeader> {
+    if let Some(header) = fs::read_to_string(header_path(cache_file))
+        .ok()
+        .and_then(|json| serde_json::from_str(&json).ok())
+    {
+        return Some(header);
+    }
+    read_stored_header(cache_file)
+}
+
+/// Read the summary fields from the entry in `cache_file` itself, ignoring
+/// its header file.
+fn read_stored_header(cache_file: &Path) -> Option<EntryHeader> {
+    let json = fs::read_to_string(cache_file).ok()?;
+    serde_json::from_str(&json).ok()
+}
+
+/// Read and parse the entry in `cache_file`, or `None` if there is none.
+fn parse_entry_file(cache_file: &Path) -> Result<Option<serde_json::Result<CacheEntry>>> {
+    match fs::read_to_string(cache_file) {
+        Ok(json) => Ok(Some(serde_json::from_str(&json))),
+        // Never stored, or removed by a concurrent `clear` or quarantine
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(Error::cache_io(cache_file, e)),
     }
 }
 
-impl Default for Cache {
-    fn default() -> Self {
-        Self::new().expect("Failed to create cache")
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_cache_key_is_stable() {
+        let cache_dir = tempdir().unwrap();
+        let cache = Cache::with_dir(cache_dir.path()).unwrap();
+        let path = Path::new("/nonexistent/./rudu-key-test");
+
+        // SHA-256 of "v4\0/nonexistent/rudu-key-test\0{\"archives\":false,\"hardlinks\":\"once\",\"follow_symlinks\":false}"
+        assert_eq!(
+            cache.cache_key(path, &ScanOptions::default()),
+            "91ddd6b2b06b5c203f7f390c25925e3e391aabb2b3ed37f2c8d63a952e54573c"
+        );
+        let archives = ScanOptions {
+            archives: true,
+            ..Default::default()
+        };
+        assert_ne!(
+            cache.cache_key(path, &archives),
+            cache.cache_key(path, &ScanOptions::default())
+        );
+    }
+
+    #[test]
+    fn test_options_are_cached_separately() {
+        let dir = tempdir().unwrap();
+        let cache_dir = tempdir().unwrap();
+        let cache = Cache::with_dir(cache_dir.path()).unwrap();
+        let archives = ScanOptions {
+            archives: true,
+            ..Default::default()
+        };
+
+        cache.store(dir.path(), &HashMap::new(), 1).unwrap();
+        cache
+            .store_with_options(dir.path(), &HashMap::new(), 2, &HashMap::new(), &archives)
+            .unwrap();
+
+        let plain = cache.retrieve(dir.path(), 3600).unwrap().unwrap();
+        let with_archives = cache
+            .retrieve_with_options(dir.path(), &archives, 3600)
+            .unwrap()
+            .unwrap();
+        assert_eq!((plain.total_files, with_archives.total_files), (1, 2));
+    }
+
+    #[test]
+    fn test_entry_for_another_path_is_a_miss() {
+        let dir = tempdir().unwrap();
+        let other = tempdir().unwrap();
+        let cache_dir = tempdir().unwrap();
+        let cache = Cache::with_dir(cache_dir.path()).unwrap();
+
+        cache.store(other.path(), &HashMap::new(), 1).unwrap();
+        let options = ScanOptions::default();
+        fs::rename(
+            cache.cache_file_path(other.path(), &options),
+            cache.cache_file_path(dir.path(), &options),
+        )
+        .unwrap();
+
+        assert!(cache.retrieve(dir.path(), 3600).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_old_entries_are_migrated() {
+        let dir = tempdir().unwrap();
+        let cache_dir = tempdir().unwrap();
+        let entry = CacheEntry {
+            sizes: HashMap::from([(dir.path().to_path_buf(), 42)]),
+            total_files: 3,
+            timestamp: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap()
+                .as_secs(),
+            base_path: dir.path().to_path_buf(),
+            hardlinks: Vec::new(),
+            hardlink_dirs: Vec::new(),
+            options: ScanOptions::default(),
+            hardlink_stats: HardlinkStats::default(),
+            symlinks: SymlinkStats::default(),
+            file_counts: HashMap::new(),
+            modified: HashMap::new(),
+        };
+        // Written by earlier versions, keyed by a 64-bit hash
+        fs::write(
+            cache_dir.path().join("9f86d081884c7d65.json"),
+            serde_json::to_string(&entry).unwrap(),
+        )
+        .unwrap();
+        fs::write(cache_dir.path().join("1234abcd.json"), "{ not json").unwrap();
+
+        let cache = Cache::with_dir(cache_dir.path()).unwrap();
+        let migrated = cache.retrieve(dir.path(), 3600).unwrap().unwrap();
+        assert_eq!(migrated.sizes[dir.path()], 42);
+        assert_eq!(cache.stats().unwrap().0, 1);
+        let cache_file = cache.cache_file_path(dir.path(), &ScanOptions::default());
+        assert!(header_path(&cache_file).exists());
+        let quarantined = fs::read_dir(cache_dir.path().join(QUARANTINE_DIR)).unwrap();
+        assert_eq!(quarantined.count(), 1);
+    }
+
+    #[test]
+    fn test_retrieve_below_reads_only_matching_entries() {
+        let cache_dir = tempdir().unwrap();
+        let cache = Cache::with_dir(cache_dir.path()).unwrap();
+        let archives = ScanOptions {
+            archives: true,
+            ..Default::default()
+        };
+        let (child, other, sibling) = (
+            Path::new("/data/child"),
+            Path::new("/data/other"),
+            Path::new("/elsewhere"),
+        );
+        cache.store(child, &HashMap::new(), 1).unwrap();
+        cache
+            .store_with_options(other, &HashMap::new(), 2, &HashMap::new(), &archives)
+            .unwrap();
+        cache.store(sibling, &HashMap::new(), 3).unwrap();
+        // Entries that do not match are never parsed, so they are not
+        // found to be corrupt
+        for (path, options) in [(other, &archives), (sibling, &ScanOptions::default())] {
+            fs::write(cache.cache_file_path(path, options), "{ not json").unwrap();
+        }
+
+        let below = cache.retrieve_below(Path::new("/data"), 3600).unwrap();
+        assert_eq!(below.len(), 1);
+        assert_eq!(below[0].base_path, child);
+        assert!(!cache_dir.path().join(QUARANTINE_DIR).exists());
+        assert_eq!(cache.list().unwrap().len(), 3);
+
+        // Headers of entries removed with them
+        assert!(cache.invalidate(child).unwrap());
+        let child_file = cache.cache_file_path(child, &ScanOptions::default());
+        assert!(!header_path(&child_file).exists());
+    }
+
+    #[test]
+    fn test_expired_entry_is_removed_only_if_unchanged() {
+        let dir = tempdir().unwrap();
+        let cache_dir = tempdir().unwrap();
+        let cache = Cache::with_dir(cache_dir.path()).unwrap();
+        cache.store(dir.path(), &HashMap::new(), 1).unwrap();
+        let cache_file = cache.cache_file_path(dir.path(), &ScanOptions::default());
+        let stored = cache.get(dir.path()).unwrap().unwrap();
+
+        // Replaced by a newer scan since the expired one was read
+        cache
+            .remove_expired(&cache_file, stored.timestamp - 1)
+            .unwrap();
+        assert!(cache_file.exists());
+
+        cache.remove_expired(&cache_file, stored.timestamp).unwrap();
+        assert!(!cache_file.exists() && !header_path(&cache_file).exists());
+    }
+
+    #[test]
+    fn test_size_limit_counts_header_files() {
+        let dirs: Vec<_> = (0..2).map(|_| tempdir().unwrap()).collect();
+        let cache_dir = tempdir().unwrap();
+        let cache = Cache::with_dir(cache_dir.path()).unwrap();
+        cache.store(dirs[0].path(), &HashMap::new(), 1).unwrap();
+        cache.store(dirs[1].path(), &HashMap::new(), 2).unwrap();
+
+        let on_disk: u64 = fs::read_dir(cache_dir.path())
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .filter(|path| path.file_name() != Some(LOCK_FILE.as_ref()))
+            .map(|path| fs::metadata(path).unwrap().len())
+            .sum();
+        assert_eq!(cache.stats().unwrap(), (2, on_disk));
+
+        // Below the size on disk, but above that of the entry files alone
+        let cache = cache.max_size(on_disk - 1);
+        cache.store(dirs[0].path(), &HashMap::new(), 1).unwrap();
+        assert_eq!(cache.stats().unwrap().0, 1);
+        let remaining = fs::read_dir(cache_dir.path()).unwrap().count();
+        // The entry, its header and the lock file
+        assert_eq!(remaining, 3);
+
+        // A header without its entry is removed by pruning
+        let stray = cache_dir.path().join(format!("stray.{HEADER_EXTENSION}"));
+        fs::write(&stray, "{}").unwrap();
+        cache.prune(3600).unwrap();
+        assert!(!stray.exists());
     }
 }
@@ -0,0 +1,49 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+/// Shared flag used to stop a running scan.
+///
+/// Clones share the same flag, so one clone can be handed to a scanner while
+/// another is cancelled from a signal handler, a UI thread or a timer.
+///
+/// # Example
+///
+/// ```rust
+/// use rudu::{CancellationToken, Scanner};
+///
+/// let token = CancellationToken::new();
+/// token.cancel();
+///
+/// let result = Scanner::new("/").cancel_token(token).scan();
+/// assert!(!result.is_complete());
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct CancellationToken {
+    cancelled: Arc<AtomicBool>,
+}
+
+impl CancellationToken {
+    /// Create a token that has not been cancelled.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Ask every scan holding this token to stop as soon as possible.
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+
+    /// Whether [`CancellationToken::cancel`] has been called.
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Relaxed)
+    }
+}
+
+/// Why a scan stopped before reading every directory.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StopReason {
+    /// The scan's [`CancellationToken`] was cancelled
+    Cancelled,
+    /// The scan's time budget expired
+    TimedOut,
+}
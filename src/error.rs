@@ -0,0 +1,110 @@
+use std::fmt;
+use std::io;
+use std::path::PathBuf;
+
+/// Errors reported by rudu.
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum Error {
+    /// The platform has no cache directory for the current user
+    NoCacheDir,
+    /// Reading or writing the cache failed
+    CacheIo {
+        /// Cache file or directory being accessed
+        path: PathBuf,
+        source: io::Error,
+    },
+    /// A cache file exists but could not be parsed
+    CorruptCache {
+        /// The unreadable cache file
+        path: PathBuf,
+        source: serde_json::Error,
+    },
+    /// A path given to scan could not be resolved
+    ResolvePath {
+        /// The path as given
+        path: PathBuf,
+        source: io::Error,
+    },
+    /// A directory or file could not be read during a scan
+    Walk {
+        /// The entry that could not be read
+        path: PathBuf,
+        source: io::Error,
+    },
+    /// A symlink being followed leads to nothing
+    BrokenSymlink {
+        /// The symlink
+        path: PathBuf,
+        source: io::Error,
+    },
+    /// A symlink being followed leads back to a directory above it, or
+    /// through too many other symlinks
+    SymlinkLoop {
+        /// The symlink
+        path: PathBuf,
+    },
+    /// A background scan ended without a result, because its thread
+    /// panicked or could not be started, or because the result was already
+    /// taken
+    ScanFailed,
+}
+
+/// Result type used throughout rudu.
+pub type Result<T, E = Error> = std::result::Result<T, E>;
+
+impl Error {
+    pub(crate) fn cache_io(path: impl Into<PathBuf>, source: io::Error) -> Self {
+        Error::CacheIo {
+            path: path.into(),
+            source,
+        }
+    }
+
+    pub(crate) fn walk(path: impl Into<PathBuf>, source: io::Error) -> Self {
+        Error::Walk {
+            path: path.into(),
+            source,
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::NoCacheDir => write!(f, "could not determine cache directory"),
+            Error::CacheIo { path, source } => {
+                write!(f, "cache I/O error at '{}': {source}", path.display())
+            }
+            Error::CorruptCache { path, source } => {
+                write!(f, "corrupt cache file '{}': {source}", path.display())
+            }
+            Error::ResolvePath { path, source } => {
+                write!(f, "failed to resolve path '{}': {source}", path.display())
+            }
+            Error::Walk { path, source } => {
+                write!(f, "failed to read '{}': {source}", path.display())
+            }
+            Error::BrokenSymlink { path, source } => {
+                write!(f, "broken symlink '{}': {source}", path.display())
+            }
+            Error::SymlinkLoop { path } => {
+                write!(f, "symlink loop at '{}'", path.display())
+            }
+            Error::ScanFailed => write!(f, "scan ended without a result"),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::NoCacheDir | Error::SymlinkLoop { .. } | Error::ScanFailed => None,
+            Error::CacheIo { source, .. }
+            | Error::ResolvePath { source, .. }
+            | Error::Walk { source, .. }
+            | Error::BrokenSymlink { source, .. } => Some(source),
+            Error::CorruptCache { source, .. } => Some(source),
+        }
+    }
+}
@@ -0,0 +1,168 @@
+use std::io::{self, Read};
+use std::path::{Component, Path, PathBuf};
+
+/// Read a list of file paths separated by newlines or NUL bytes.
+///
+/// If the input contains any NUL byte it is treated as NUL-separated (as
+/// produced by `find -print0` or `git ls-files -z`), otherwise as one path
+/// per line. Empty entries are skipped and a trailing `\r` is removed from
+/// each line.
+///
+/// # Example
+///
+/// ```rust
+/// use std::path::PathBuf;
+/// use rudu::files_from::read_file_list;
+///
+/// let files = read_file_list("src/lib.rs\nsrc/main.rs\n".as_bytes()).unwrap();
+/// assert_eq!(files, vec![PathBuf::from("src/lib.rs"), PathBuf::from("src/main.rs")]);
+/// ```
+pub fn read_file_list(mut reader: impl Read) -> io::Result<Vec<PathBuf>> {
+    let mut data = Vec::new();
+    reader.read_to_end(&mut data)?;
+
+    let entries: Vec<&[u8]> = if data.contains(&0) {
+        data.split(|&b| b == 0).collect()
+    } else {
+        data.split(|&b| b == b'\n')
+            .map(|line| line.strip_suffix(b"\r").unwrap_or(line))
+            .collect()
+    };
+
+    Ok(entries
+        .into_iter()
+        .filter(|entry| !entry.is_empty())
+        .map(path_from_bytes)
+        .collect())
+}
+
+/// Resolve every path in `files` against `cwd`, normalizing `.` and `..`.
+pub fn absolute_paths(files: &[PathBuf], cwd: &Path) -> Vec<PathBuf> {
+    files
+        .iter()
+        .map(|file| normalize_path(&cwd.join(file)))
+        .collect()
+}
+
+/// Deepest directory containing every file in `files`.
+///
+/// Paths should be absolute and normalized (see [`absolute_paths`]).
+/// Returns `None` for an empty list.
+pub fn common_base(files: &[PathBuf]) -> Option<PathBuf> {
+    let mut parents = files.iter().map(|file| file.parent().unwrap_or(file));
+    let mut base = parents.next()?.to_path_buf();
+    for parent in parents {
+        while !parent.starts_with(&base) {
+            if !base.pop() {
+                break;
+            }
+        }
+    }
+    Some(base)
+}
+
+/// Resolve `.` and `..` components lexically, without touching the filesystem.
+pub(crate) fn normalize_path(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                normalized.pop();
+            }
+            other => normalized.push(other),
+        }
+    }
+    normalized
+}
+
+#[cfg(unix)]
+fn path_from_bytes(bytes: &[u8]) -> PathBuf {
+    use std::ffi::OsStr;
+    use std::os::unix::ffi::OsStrExt;
+    PathBuf::from(OsStr::from_bytes(bytes))
+}
+
+#[cfg(not(unix))]
+fn path_from_bytes(bytes: &[u8]) -> PathBuf {
+    PathBuf::from(String::from_utf8_lossy(bytes).into_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Scanner;
+    use std::fs;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_read_newline_and_nul_lists() {
+        let lines = read_file_list("a/one\r\nb/two\n\n".as_bytes()).unwrap();
+        assert_eq!(lines, vec![PathBuf::from("a/one"), PathBuf::from("b/two")]);
+
+        let nul = read_file_list("with\nnewline\0plain\0".as_bytes()).unwrap();
+        assert_eq!(
+            nul,
+            vec![PathBuf::from("with\nnewline"), PathBuf::from("plain")]
+        );
+    }
+
+    #[test]
+    fn test_common_base() {
+        let files = absolute_paths(
+            &[
+                PathBuf::from("/data/a/x/file1"),
+                PathBuf::from("/data/a/./y/file2"),
+                PathBuf::from("/data/a/y/../z/file3"),
+            ],
+            Path::new("/"),
+        );
+        assert_eq!(files[2], PathBuf::from("/data/a/z/file3"));
+        assert_eq!(common_base(&files), Some(PathBuf::from("/data/a")));
+        assert_eq!(common_base(&[]), None);
+    }
+
+    #[test]
+    fn test_scan_listed_files_only() {
+        let dir = tempdir().unwrap();
+        let sub = dir.path().join("sub");
+        fs::create_dir(&sub).unwrap();
+        fs::write(dir.path().join("listed.txt"), "1234").unwrap(); // 4 bytes
+        fs::write(sub.join("listed.txt"), "12").unwrap(); // 2 bytes
+        fs::write(sub.join("ignored.txt"), "123456789").unwrap();
+
+        let files = vec![
+            dir.path().join("listed.txt"),
+            sub.join("listed.txt"),
+            sub.join("missing.txt"),
+            sub.clone(),
+        ];
+        let result = Scanner::new(dir.path()).scan_files(&files);
+
+        assert_eq!(result.tree.size(result.tree.root()), 6);
+        assert_eq!(result.total_files(), 2);
+        assert_eq!(result.errors, 1);
+        let sub_id = result.tree.lookup(&sub).unwrap();
+        assert_eq!(result.tree.size(sub_id), 2);
+    }
+
+    #[test]
+    fn test_scan_files_spread_over_wide_directory() {
+        // Building the tree from many sibling directories used to take time
+        // quadratic in their number
+        const WIDTH: usize = 50_000;
+        let files: Vec<PathBuf> = (0..WIDTH)
+            .map(|i| PathBuf::from(format!("/wide/dir{i}/file")))
+            .collect();
+        let mut fs = crate::filesystem::MemoryFs::new();
+        for file in &files {
+            fs.add_file(file, 3);
+        }
+        let result = Scanner::new("/wide").filesystem(fs).scan_files(&files);
+
+        assert_eq!(result.errors, 0);
+        assert_eq!(result.total_files(), WIDTH);
+        assert_eq!(result.tree.len(), WIDTH + 1);
+        assert_eq!(result.tree.size(result.tree.root()), 3 * WIDTH as u64);
+    }
+}
@@ -0,0 +1,515 @@
+use std::collections::HashMap;
+use std::ffi::OsString;
+use std::fs;
+use std::io;
+use std::path::{Component, Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+/// Kind of a directory entry. Symlinks are reported as such, not followed.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum FileKind {
+    /// Regular file
+    #[default]
+    File,
+    /// Directory
+    Dir,
+    /// Symbolic link
+    Symlink,
+    /// Device, socket, FIFO or anything else
+    Other,
+}
+
+/// The parts of file metadata the scanner uses.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Metadata {
+    /// What kind of entry this is
+    pub kind: FileKind,
+    /// Apparent size in bytes
+    pub len: u64,
+    /// Device the entry lives on, or `0` where unavailable
+    pub dev: u64,
+    /// Inode number, or `0` where unavailable
+    pub ino: u64,
+    /// Number of hard links to the inode
+    pub nlink: u64,
+    /// Last modification time in seconds since the Unix epoch, or `0` where unavailable
+    pub modified: u64,
+}
+
+impl Metadata {
+    /// Whether this is a regular file.
+    pub fn is_file(&self) -> bool {
+        self.kind == FileKind::File
+    }
+}
+
+/// One entry of a directory listing.
+#[derive(Debug)]
+pub struct DirEntry {
+    /// Full path of the entry
+    pub path: PathBuf,
+    /// Kind of entry, as reported by the directory listing
+    pub kind: FileKind,
+    /// Metadata for regular files, if the source can provide it cheaply while
+    /// listing. When `None`, the scanner calls [`FileSystem::symlink_metadata`].
+    pub metadata: Option<io::Result<Metadata>>,
+}
+
+/// Source of directory listings and file metadata for the scanner.
+///
+/// [`RealFs`] reads the local filesystem; [`MemoryFs`] serves a tree built in
+/// memory, which makes edge cases such as hardlinks, permission errors and
+/// very large trees reproducible in tests and benchmarks.
+///
+/// # Example
+///
+/// ```rust
+/// use rudu::filesystem::MemoryFs;
+/// use rudu::Scanner;
+///
+/// let mut fs = MemoryFs::new();
+/// fs.add_file("/data/logs/app.log", 300);
+/// fs.add_file("/data/readme.txt", 20);
+///
+/// let result = Scanner::new("/data").filesystem(fs).scan();
+/// assert_eq!(result.tree.size(result.tree.root()), 320);
+/// ```
+pub trait FileSystem: Send + Sync {
+    /// List the entries of the directory at `path`.
+    fn read_dir(&self, path: &Path) -> io::Result<Vec<DirEntry>>;
+
+    /// Metadata for `path` itself, without following a final symlink.
+    fn symlink_metadata(&self, path: &Path) -> io::Result<Metadata>;
+
+    /// Metadata for whatever `path` leads to, following symlinks.
+    ///
+    /// A chain of symlinks that never reaches an entry fails with an error
+    /// recognised by [`is_symlink_loop`].
+    fn metadata(&self, path: &Path) -> io::Result<Metadata>;
+}
+
+/// OS error code for a path that runs into too many levels of symlinks.
+#[cfg(any(target_os = "linux", target_os = "android"))]
+const SYMLINK_LOOP: i32 = 40; // ELOOP
+#[cfg(all(unix, not(any(target_os = "linux", target_os = "android"))))]
+const SYMLINK_LOOP: i32 = 62; // ELOOP on macOS and the BSDs
+#[cfg(not(unix))]
+const SYMLINK_LOOP: i32 = 1921; // ERROR_CANT_RESOLVE_FILENAME
+
+/// Whether `error` comes from resolving a path through a loop of symlinks.
+pub fn is_symlink_loop(error: &io::Error) -> bool {
+    error.raw_os_error() == Some(SYMLINK_LOOP)
+}
+
+/// The local filesystem, via `std::fs`.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct RealFs;
+
+impl FileSystem for RealFs {
+    fn read_dir(&self, path: &Path) -> io::Result<Vec<DirEntry>> {
+        let mut entries = Vec::new();
+        for entry in fs::read_dir(path)?.filter_map(Result::ok) {
+            // The kind usually comes straight from the listing, without a stat call
+            let kind = match entry.file_type() {
+                Ok(file_type) => kind_of(&file_type),
+                Err(_) => continue,
+            };
+            // Stat'ing through the entry is relative to the open directory
+            let metadata = (kind == FileKind::File)
+                .then(|| entry.metadata().map(|metadata| convert(&metadata)));
+            entries.push(DirEntry {
+                path: entry.path(),
+                kind,
+                metadata,
+            });
+        }
+        Ok(entries)
+    }
+
+    fn symlink_metadata(&self, path: &Path) -> io::Result<Metadata> {
+        fs::symlink_metadata(path).map(|metadata| convert(&metadata))
+    }
+
+    fn metadata(&self, path: &Path) -> io::Result<Metadata> {
+        fs::metadata(path).map(|metadata| convert(&metadata))
+    }
+}
+
+fn kind_of(file_type: &fs::FileType) -> FileKind {
+    if file_type.is_symlink() {
+        FileKind::Symlink
+    } else if file_type.is_dir() {
+        FileKind::Dir
+    } else if file_type.is_file() {
+        FileKind::File
+    } else {
+        FileKind::Other
+    }
+}
+
+fn convert(metadata: &fs::Metadata) -> Metadata {
+    #[cfg(unix)]
+    let (dev, ino, nlink) = {
+        use std::os::unix::fs::MetadataExt;
+        (metadata.dev(), metadata.ino(), metadata.nlink())
+    };
+    #[cfg(not(unix))]
+    let (dev, ino, nlink) = (0, 0, 1);
+
+    let modified = metadata
+        .modified()
+        .ok()
+        .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+        .map_or(0, |since| since.as_secs());
+
+    Metadata {
+        kind: kind_of(&metadata.file_type()),
+        len: metadata.len(),
+        dev,
+        ino,
+        nlink,
+        modified,
+    }
+}
+
+/// Device number reported for every entry of a [`MemoryFs`].
+const MEMORY_DEV: u64 = 1;
+
+/// Symlinks followed while resolving one path before giving up, as Linux does.
+const MAX_SYMLINKS: usize = 40;
+
+struct MemoryNode {
+    metadata: Metadata,
+    children: Vec<OsString>,
+    denied: bool,
+    /// Where a symlink points, relative to its directory unless absolute
+    target: Option<PathBuf>,
+}
+
+/// An in-memory directory tree.
+///
+/// Parent directories are created as needed, every entry gets its own inode
+/// unless added with [`MemoryFs::add_hardlink`], and [`MemoryFs::deny`]
+/// makes an entry fail with a permission error. Symlinks added with
+/// [`MemoryFs::add_symlink_to`] resolve like real ones, including through
+/// intermediate path components.
+#[derive(Default)]
+pub struct MemoryFs {
+    nodes: HashMap<PathBuf, MemoryNode>,
+    /// Link count per inode
+    links: HashMap<u64, u64>,
+    next_ino: u64,
+}
+
+impl MemoryFs {
+    /// Create an empty filesystem.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Generate a balanced tree under `root`: `width` subdirectories per
+    /// directory, `depth` levels deep, with `files` files of `file_size`
+    /// bytes in every directory.
+    pub fn synthetic(
+        root: impl AsRef<Path>,
+        width: usize,
+        depth: usize,
+        files: usize,
+        file_size: u64,
+    ) -> Self {
+        let mut fs = Self::new();
+        let mut level = vec![root.as_ref().to_path_buf()];
+        fs.add_dir(&level[0]);
+        for remaining in (0..=depth).rev() {
+            let mut next = Vec::with_capacity(level.len() * width);
+            for dir in &level {
+                for i in 0..files {
+                    fs.add_file(dir.join(format!("file{i}")), file_size);
+                }
+                if remaining > 0 {
+                    for i in 0..width {
+                        let child = dir.join(format!("dir{i}"));
+                        fs.add_dir(&child);
+                        next.push(child);
+                    }
+                }
+            }
+            level = next;
+        }
+        fs
+    }
+
+    /// Add a directory, along with any missing parents.
+    pub fn add_dir(&mut self, path: impl AsRef<Path>) {
+        self.insert(path.as_ref(), FileKind::Dir, 0, None);
+    }
+
+    /// Add a regular file of `len` bytes.
+    pub fn add_file(&mut self, path: impl AsRef<Path>, len: u64) {
+        self.insert(path.as_ref(), FileKind::File, len, None);
+    }
+
+    /// Add a symbolic link without a target, which behaves like a broken link
+    /// when followed.
+    pub fn add_symlink(&mut self, path: impl AsRef<Path>) {
+        self.insert(path.as_ref(), FileKind::Symlink, 0, None);
+    }
+
+    /// Add a symbolic link to `target`, which need not exist. Relative targets
+    /// are resolved from the directory holding the link.
+    pub fn add_symlink_to(&mut self, path: impl AsRef<Path>, target: impl AsRef<Path>) {
+        let path = path.as_ref();
+        let len = target.as_ref().as_os_str().len() as u64;
+        self.insert(path, FileKind::Symlink, len, None);
+        if let Some(node) = self.nodes.get_mut(path) {
+            node.target = Some(target.as_ref().to_path_buf());
+        }
+    }
+
+    /// Add `path` as another hard link to the file at `target`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `target` has not been added.
+    pub fn add_hardlink(&mut self, path: impl AsRef<Path>, target: impl AsRef<Path>) {
+        let target = self.nodes[target.as_ref()].metadata;
+        self.insert(path.as_ref(), target.kind, target.len, Some(target.ino));
+    }
+
+    /// Set the modification time of the file or directory at `path`, in
+    /// seconds since the Unix epoch, for every hard link to it.
+    pub fn set_modified(&mut self, path: impl AsRef<Path>, modified: u64) {
+        let Some(ino) = self.nodes.get(path.as_ref()).map(|node| node.metadata.ino) else {
+            return;
+        };
+        for node in self.nodes.values_mut() {
+            if node.metadata.ino == ino {
+                node.metadata.modified = modified;
+            }
+        }
+    }
+
+    /// Make listing or stat'ing `path` fail with [`io::ErrorKind::PermissionDenied`].
+    pub fn deny(&mut self, path: impl AsRef<Path>) {
+        if let Some(node) = self.nodes.get_mut(path.as_ref()) {
+            node.denied = true;
+        }
+    }
+
+    fn insert(&mut self, path: &Path, kind: FileKind, len: u64, ino: Option<u64>) {
+        if self.nodes.contains_key(path) {
+            return;
+        }
+        if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+            self.add_dir(parent);
+            if let (Some(node), Some(name)) = (self.nodes.get_mut(parent), path.file_name()) {
+                node.children.push(name.to_os_string());
+            }
+        }
+
+        let ino = ino.unwrap_or_else(|| {
+            self.next_ino += 1;
+            self.next_ino
+        });
+        *self.links.entry(ino).or_insert(0) += 1;
+        self.nodes.insert(
+            path.to_path_buf(),
+            MemoryNode {
+                metadata: Metadata {
+                    kind,
+                    len,
+                    dev: MEMORY_DEV,
+                    ino,
+                    nlink: 0,
+                    modified: 0,
+                },
+                children: Vec::new(),
+                denied: false,
+                target: None,
+            },
+        );
+    }
+
+    /// Follow the symlinks along `path`, including a final one if
+    /// `follow_last` is set, returning the path of the entry it leads to.
+    fn resolve(&self, path: &Path, follow_last: bool) -> io::Result<PathBuf> {
+        let mut followed = 0;
+        self.resolve_from(PathBuf::new(), path, follow_last, &mut followed)
+    }
+
+    fn resolve_from(
+        &self,
+        mut resolved: PathBuf,
+        path: &Path,
+        follow_last: bool,
+        followed: &mut usize,
+    ) -> io::Result<PathBuf> {
+        let mut components = path.components().peekable();
+        while let Some(component) = components.next() {
+            match component {
+                Component::CurDir => continue,
+                Component::ParentDir => {
+                    resolved.pop();
+                    continue;
+                }
+                other => resolved.push(other),
+            }
+            let node = match self.nodes.get(&resolved) {
+                Some(node) if node.metadata.kind == FileKind::Symlink => node,
+                _ => continue,
+            };
+            if components.peek().is_none() && !follow_last {
+                continue;
+            }
+            let Some(target) = &node.target else {
+                return Err(not_found());
+            };
+
+            *followed += 1;
+            if *followed > MAX_SYMLINKS {
+                return Err(io::Error::from_raw_os_error(SYMLINK_LOOP));
+            }
+            resolved.pop();
+            resolved = self.resolve_from(resolved, target, true, followed)?;
+        }
+        Ok(resolved)
+    }
+
+    fn node(&self, path: &Path) -> io::Result<&MemoryNode> {
+        match self.nodes.get(path) {
+            Some(node) if node.denied => Err(io::Error::new(
+                io::ErrorKind::PermissionDenied,
+                "permission denied",
+            )),
+            Some(node) => Ok(node),
+            None => Err(not_found()),
+        }
+    }
+}
+
+fn not_found() -> io::Error {
+    io::Error::new(io::ErrorKind::NotFound, "no such file or directory")
+}
+
+impl FileSystem for MemoryFs {
+    fn read_dir(&self, path: &Path) -> io::Result<Vec<DirEntry>> {
+        let resolved = self.resolve(path, true)?;
+        let node = self.node(&resolved)?;
+        if node.metadata.kind != FileKind::Dir {
+            return Err(io::Error::other("not a directory"));
+        }
+        // Entries are listed under the path as given, like a real listing
+        Ok(node
+            .children
+            .iter()
+            .map(|name| DirEntry {
+                kind: self.nodes[&resolved.join(name)].metadata.kind,
+                metadata: None,
+                path: path.join(name),
+            })
+            .collect())
+    }
+
+    fn symlink_metadata(&self, path: &Path) -> io::Result<Metadata> {
+        let node = self.node(&self.resolve(path, false)?)?;
+        Ok(Metadata {
+            nlink: self.links[&node.metadata.ino],
+            ..node.metadata
+        })
+    }
+
+    fn metadata(&self, path: &Path) -> io::Result<Metadata> {
+        self.symlink_metadata(&self.resolve(path, true)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_memory_fs_listing_and_links() {
+        let mut fs = MemoryFs::new();
+        fs.add_file("/a/b/file", 10);
+        fs.add_hardlink("/a/link", "/a/b/file");
+        fs.add_symlink("/a/sym");
+
+        let mut names: Vec<_> = fs
+            .read_dir(Path::new("/a"))
+            .unwrap()
+            .into_iter()
+            .map(|entry| (entry.path, entry.kind))
+            .collect();
+        names.sort_by(|a, b| a.0.cmp(&b.0));
+        assert_eq!(
+            names,
+            vec![
+                (PathBuf::from("/a/b"), FileKind::Dir),
+                (PathBuf::from("/a/link"), FileKind::File),
+                (PathBuf::from("/a/sym"), FileKind::Symlink),
+            ]
+        );
+
+        let file = fs.symlink_metadata(Path::new("/a/b/file")).unwrap();
+        let link = fs.symlink_metadata(Path::new("/a/link")).unwrap();
+        assert_eq!((file.ino, file.nlink, link.len), (link.ino, 2, 10));
+    }
+
+    #[test]
+    fn test_memory_fs_denied_entries() {
+        let mut fs = MemoryFs::new();
+        fs.add_file("/secret/file", 1);
+        fs.deny("/secret");
+
+        let err = fs.read_dir(Path::new("/secret")).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::PermissionDenied);
+        assert!(fs.symlink_metadata(Path::new("/secret/file")).is_ok());
+        assert_eq!(
+            fs.read_dir(Path::new("/missing")).unwrap_err().kind(),
+            io::ErrorKind::NotFound
+        );
+    }
+
+    #[test]
+    fn test_memory_fs_symlinks() {
+        let mut fs = MemoryFs::new();
+        fs.add_file("/data/sub/file", 10);
+        fs.add_symlink_to("/links/rel", "../data");
+        fs.add_symlink_to("/links/abs", "/data/sub/file");
+        fs.add_symlink_to("/links/chain", "rel/sub");
+        fs.add_symlink_to("/links/self", "self");
+        fs.add_symlink("/links/dangling");
+
+        let file = fs.metadata(Path::new("/links/rel/sub/file")).unwrap();
+        assert_eq!(file.len, 10);
+        assert_eq!(fs.metadata(Path::new("/links/abs")).unwrap().ino, file.ino);
+        let listed: Vec<_> = fs
+            .read_dir(Path::new("/links/chain"))
+            .unwrap()
+            .into_iter()
+            .map(|entry| entry.path)
+            .collect();
+        assert_eq!(listed, vec![PathBuf::from("/links/chain/file")]);
+        assert_eq!(
+            fs.symlink_metadata(Path::new("/links/rel")).unwrap().kind,
+            FileKind::Symlink
+        );
+
+        let looped = fs.metadata(Path::new("/links/self")).unwrap_err();
+        assert!(is_symlink_loop(&looped));
+        let broken = fs.metadata(Path::new("/links/dangling")).unwrap_err();
+        assert_eq!(broken.kind(), io::ErrorKind::NotFound);
+    }
+
+    #[test]
+    fn test_synthetic_tree_shape() {
+        let fs = MemoryFs::synthetic("root", 3, 2, 4, 100);
+        // 1 + 3 + 9 directories, each holding 4 files
+        let dirs = fs
+            .nodes
+            .values()
+            .filter(|n| n.metadata.kind == FileKind::Dir);
+        assert_eq!(dirs.count(), 13);
+        assert_eq!(fs.nodes.len(), 13 * 5);
+    }
+}
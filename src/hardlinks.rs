@@ -0,0 +1,286 @@
+use crate::filesystem::Metadata;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::path::PathBuf;
+
+/// How files with several hard links are counted.
+///
+/// Whatever the policy, the result does not depend on the order in which
+/// worker threads reach the links.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum HardlinkPolicy {
+    /// Count each inode once, in the directory of its lexicographically
+    /// first path, like `du`
+    #[default]
+    Once,
+    /// Count the full size at every link, like `du -l`
+    All,
+    /// Split the size evenly between the links found, so every linking
+    /// directory carries its share
+    Proportional,
+}
+
+impl fmt::Display for HardlinkPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            HardlinkPolicy::Once => "once",
+            HardlinkPolicy::All => "all",
+            HardlinkPolicy::Proportional => "proportional",
+        })
+    }
+}
+
+/// Files reached through more than one hard link during a scan.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HardlinkStats {
+    /// Inodes reached through two or more paths
+    pub inodes: u64,
+    /// Paths leading to those inodes
+    pub links: u64,
+    /// Size of those inodes, each counted once
+    pub bytes: u64,
+    /// Bytes that counting every link in full would add on top of `bytes`
+    pub shared_bytes: u64,
+}
+
+impl HardlinkStats {
+    /// Add the counts of `other`, e.g. from a separately scanned subtree.
+    pub fn merge(&mut self, other: &HardlinkStats) {
+        self.inodes += other.inodes;
+        self.links += other.links;
+        self.bytes += other.bytes;
+        self.shared_bytes += other.shared_bytes;
+    }
+}
+
+/// A path to a multiply-linked file, or a symlink to a file, held back
+/// until every path to it is known.
+pub(crate) struct Link {
+    /// Node id of the directory holding the link during a directory walk.
+    /// File lists locate the directory from the path instead.
+    pub dir: u32,
+    pub path: PathBuf,
+    /// Metadata of the file, for a symlink that of its target
+    pub metadata: Metadata,
+    /// Whether the file was reached by following a symlink
+    pub via_symlink: bool,
+}
+
+impl Link {
+    fn key(&self) -> (u64, u64) {
+        (self.metadata.dev, self.metadata.ino)
+    }
+}
+
+/// Links with the bytes each one contributes, as decided by [`resolve`].
+pub(crate) struct Resolved {
+    /// Every link, sorted by inode and path, with its counted bytes
+    pub links: Vec<(Link, u64)>,
+    /// Size of every deduplicated inode, keyed by `(device, inode)`. Empty
+    /// under [`HardlinkPolicy::All`], since no link is deduplicated.
+    pub inodes: HashMap<(u64, u64), u64>,
+    /// Directories holding the links that count bytes of each inode in
+    /// `inodes`, with the bytes counted there
+    pub dirs: HashMap<(u64, u64), Vec<(PathBuf, u64)>>,
+    pub stats: HardlinkStats,
+}
+
+/// Decide how many bytes each link contributes under `policy`.
+///
+/// Symlinks never add to a file counted through a hard link, or listed in
+/// `counted` as already counted elsewhere; a file reached only through
+/// symlinks is counted once, at the first of them.
+pub(crate) fn resolve(
+    mut links: Vec<Link>,
+    policy: HardlinkPolicy,
+    counted: &HashSet<(u64, u64)>,
+) -> Resolved {
+    links.sort_by(|a, b| {
+        a.key()
+            .cmp(&b.key())
+            .then(a.via_symlink.cmp(&b.via_symlink))
+            .then_with(|| a.path.cmp(&b.path))
+    });
+
+    let mut stats = HardlinkStats::default();
+    let mut inodes = HashMap::new();
+    let mut dirs: HashMap<(u64, u64), Vec<(PathBuf, u64)>> = HashMap::new();
+    let mut resolved = Vec::with_capacity(links.len());
+    let mut links = links.into_iter().peekable();
+    while let Some(first) = links.next() {
+        let key = first.key();
+        let mut group = vec![first];
+        while let Some(link) = links.next_if(|link| link.key() == key) {
+            group.push(link);
+        }
+
+        let len = group[0].metadata.len;
+        // Hard links sort before symlinks, so they come first
+        let paths = group.iter().filter(|link| !link.via_symlink).count() as u64;
+        if paths > 1 {
+            stats.inodes += 1;
+            stats.links += paths;
+            stats.bytes += len;
+            stats.shared_bytes += len * (paths - 1);
+        }
+        if policy != HardlinkPolicy::All || paths == 0 {
+            inodes.insert(key, len);
+        }
+
+        let elsewhere = counted.contains(&key);
+        for (index, link) in group.into_iter().enumerate() {
+            let index = index as u64;
+            let bytes = match policy {
+                _ if elsewhere || (link.via_symlink && paths > 0) => 0,
+                _ if paths == 0 => u64::from(index == 0) * len,
+                HardlinkPolicy::All => len,
+                HardlinkPolicy::Once if index == 0 => len,
+                HardlinkPolicy::Once => 0,
+                // The remainder goes to the first paths, so shares add up to the size
+                HardlinkPolicy::Proportional => len / paths + u64::from(index < len % paths),
+            };
+            if bytes > 0 && inodes.contains_key(&key) {
+                if let Some(dir) = link.path.parent() {
+                    dirs.entry(key)
+                        .or_default()
+                        .push((dir.to_path_buf(), bytes));
+                }
+            }
+            resolved.push((link, bytes));
+        }
+    }
+    Resolved {
+        links: resolved,
+        inodes,
+        dirs,
+        stats,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn link(dir: u32, path: &str, ino: u64, len: u64) -> Link {
+        Link {
+            dir,
+            path: PathBuf::from(path),
+            metadata: Metadata {
+                len,
+                dev: 1,
+                ino,
+                nlink: 3,
+                ..Default::default()
+            },
+            via_symlink: false,
+        }
+    }
+
+    fn counted(policy: HardlinkPolicy) -> Vec<(String, u64)> {
+        let links = vec![
+            link(2, "/z/c", 7, 10),
+            link(1, "/a/b", 7, 10),
+            link(3, "/m/x", 7, 10),
+            link(1, "/a/only", 8, 5),
+        ];
+        resolve(links, policy, &HashSet::new())
+            .links
+            .into_iter()
+            .map(|(link, bytes)| (link.path.display().to_string(), bytes))
+            .collect()
+    }
+
+    #[test]
+    fn test_policies() {
+        assert_eq!(
+            counted(HardlinkPolicy::Once),
+            vec![
+                ("/a/b".into(), 10),
+                ("/m/x".into(), 0),
+                ("/z/c".into(), 0),
+                ("/a/only".into(), 5)
+            ]
+        );
+        assert_eq!(
+            counted(HardlinkPolicy::All)
+                .iter()
+                .map(|c| c.1)
+                .sum::<u64>(),
+            35
+        );
+        assert_eq!(
+            counted(HardlinkPolicy::Proportional),
+            vec![
+                ("/a/b".into(), 4),
+                ("/m/x".into(), 3),
+                ("/z/c".into(), 3),
+                ("/a/only".into(), 5)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_symlinks_never_count_twice() {
+        let symlink = |dir, path, ino| Link {
+            via_symlink: true,
+            ..link(dir, path, ino, 10)
+        };
+        let links = vec![
+            // Hard links to inode 7 and a symlink to it
+            symlink(4, "/a/to-b", 7),
+            link(2, "/b", 7, 10),
+            link(3, "/c", 7, 10),
+            // Inode 8 is only reached through symlinks
+            symlink(5, "/y", 8),
+            symlink(4, "/x", 8),
+            // Inode 9 was counted at a path with a single link
+            symlink(4, "/z", 9),
+        ];
+        let counted = HashSet::from([(1, 9)]);
+        let resolved = resolve(links, HardlinkPolicy::All, &counted);
+        let bytes: Vec<_> = resolved
+            .links
+            .iter()
+            .map(|(link, bytes)| (link.path.to_str().unwrap(), *bytes))
+            .collect();
+        assert_eq!(
+            bytes,
+            vec![
+                ("/b", 10),
+                ("/c", 10),
+                ("/a/to-b", 0),
+                ("/x", 10),
+                ("/y", 0),
+                ("/z", 0)
+            ]
+        );
+        assert_eq!((resolved.stats.inodes, resolved.stats.links), (1, 2));
+        // Only targets reached solely through symlinks are deduplicated under `All`
+        assert_eq!(resolved.inodes.len(), 2);
+    }
+
+    #[test]
+    fn test_stats_only_count_shared_inodes() {
+        let links = vec![
+            link(1, "/a", 7, 10),
+            link(2, "/b", 7, 10),
+            link(1, "/c", 8, 5),
+        ];
+        let resolved = resolve(links, HardlinkPolicy::Once, &HashSet::new());
+        assert_eq!(resolved.inodes.len(), 2);
+        assert_eq!(
+            resolved.stats,
+            HardlinkStats {
+                inodes: 1,
+                links: 2,
+                bytes: 10,
+                shared_bytes: 10,
+            }
+        );
+        assert!(resolve(Vec::new(), HardlinkPolicy::All, &HashSet::new())
+            .inodes
+            .is_empty());
+    }
+}
@@ -1,21 +1,47 @@
-use colored::*;
-use crossbeam_channel::bounded;
-use dashmap::DashMap;
 pub use rayon::prelude::*; // Re-export for main.rs
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::path::{Path, PathBuf};
-use std::sync::atomic::{AtomicUsize, Ordering};
-use std::sync::Arc;
-use std::thread;
-use std::time::{Duration, Instant};
-use walkdir::{DirEntry, WalkDir};
+use std::sync::Mutex;
+use std::time::Duration;
+use walkdir::DirEntry;
 
+pub mod archive;
+#[cfg(feature = "async")]
+pub mod async_scan;
 pub mod cache;
-pub use cache::Cache;
+pub mod cancel;
+pub mod error;
+pub mod files_from;
+pub mod filesystem;
+pub mod hardlinks;
+pub mod manifest;
+pub mod progress;
+#[cfg(test)]
+mod random_trees;
+pub mod scanner;
+pub mod symlinks;
+pub mod tree;
+pub mod units;
+pub mod visit;
+pub use cache::{Cache, CacheEntry, CacheStatus, EntryInfo, PruneStats};
+pub use cancel::{CancellationToken, StopReason};
+pub use error::{Error, Result};
+pub use hardlinks::{HardlinkPolicy, HardlinkStats};
+pub use progress::{ProgressDisplay, ScanProgress};
+pub use scanner::{ScanOptions, ScanResult, Scanner};
+pub use symlinks::SymlinkStats;
+pub use tree::{DirTree, NodeId};
+pub use visit::{Aggregator, FileEntry};
 
 /// Returns `true` if the entry is a regular file (not a directory or symlink).
 ///
-/// This filter ensures we only aggregate actual file sizes.
+/// This filter ensures we only aggregate actual file sizes. It is the only
+/// reason `walkdir` is still a dependency; both will be removed in the next
+/// major release.
+#[deprecated(
+    since = "0.2.7",
+    note = "scans no longer use walkdir; use `filesystem::Metadata::is_file` instead"
+)]
 pub fn is_file(entry: &DirEntry) -> bool {
     entry.file_type().is_file()
 }
@@ -41,228 +67,570 @@ pub fn is_file(entry: &DirEntry) -> bool {
 /// println!("Size of /tmp/mydir: {} bytes", sizes[Path::new("/tmp/mydir")]);
 /// ```
 pub fn compute_dir_sizes(base: &Path) -> HashMap<PathBuf, u64> {
-    let (sizes, _, _) = compute_dir_sizes_with_progress(base, true);
-    sizes
+    DirSizes::from(Scanner::new(base).scan()).sizes
 }
 
-/// Compute directory sizes with caching support
-pub fn compute_dir_sizes_with_cache(
-    base: &Path,
-    quiet: bool,
-    use_cache: bool,
-    max_cache_age_hours: u64,
-) -> (HashMap<PathBuf, u64>, usize, Duration) {
-    if use_cache {
-        if let Ok(cache) = Cache::new() {
-            // Try to retrieve from cache first
-            if let Ok(Some(cached_entry)) = cache.retrieve(base, max_cache_age_hours * 3600) {
-                if !quiet {
-                    eprintln!(
-                        "🚀 {} {}",
-                        "Using cached results for".bright_green().bold(),
-                        base.display().to_string().bright_white()
-                    );
-                }
-                return (
-                    cached_entry.sizes,
-                    cached_entry.total_files,
-                    Duration::from_secs(0),
-                );
-            }
+/// Canonicalize a path given to scan, so that roots can be compared and cached reliably.
+pub fn resolve_root(path: &Path) -> Result<PathBuf> {
+    path.canonicalize().map_err(|source| Error::ResolvePath {
+        path: path.to_path_buf(),
+        source,
+    })
+}
 
-            // Check if we can use a parent directory's cache for this subdirectory
-            if let Some(parent) = base.parent() {
-                if let Ok(Some(parent_cache)) = cache.retrieve(parent, max_cache_age_hours * 3600) {
-                    if let Some((filtered_sizes, file_count)) =
-                        cache.can_use_for_subdir(&parent_cache, base)
-                    {
-                        if !quiet {
-                            eprintln!(
-                                "🚀 {} {}",
-                                "Using parent cache for".bright_green().bold(),
-                                base.display().to_string().bright_white()
-                            );
-                        }
-                        return (filtered_sizes, file_count, Duration::from_secs(0));
-                    }
-                }
-            }
+/// Flat directory size map plus scan metadata, as returned by [`scan_with_cache`].
+pub struct DirSizes {
+    /// Total size of each directory's subtree
+    pub sizes: HashMap<PathBuf, u64>,
+    /// Number of files in each directory's subtree, where known; results
+    /// cached by older versions have none
+    pub file_counts: HashMap<PathBuf, u64>,
+    /// Newest file modification time in each directory's subtree, in
+    /// seconds since the Unix epoch, where known
+    pub modified: HashMap<PathBuf, u64>,
+    /// Total number of files
+    pub total_files: usize,
+    /// Time spent scanning (zero for cache hits)
+    pub duration: Duration,
+    /// Why the scan stopped early, or `None` if it completed
+    pub stopped: Option<StopReason>,
+    /// Multiply-linked inodes counted, keyed by `(device, inode)`, with the
+    /// bytes this result counts for them: their full size, unless the result
+    /// was taken from a cached scan of a parent directory that counted the
+    /// inode partly outside this one
+    pub hardlinks: HashMap<(u64, u64), u64>,
+    /// Directories holding the links counted for each inode in `hardlinks`,
+    /// with the bytes counted in each
+    pub hardlink_dirs: HashMap<(u64, u64), Vec<(PathBuf, u64)>>,
+    /// Files reached through more than one hard link
+    pub hardlink_stats: HardlinkStats,
+    /// Symlinks followed, broken and looping
+    pub symlinks: SymlinkStats,
+    /// Directories and files that could not be read
+    pub errors: u64,
+    /// Compressed size of directories inside scanned archives, where known
+    pub compressed: HashMap<PathBuf, u64>,
+    /// Whether the result came from the cache or was stored in it
+    pub cache: CacheStatus,
+}
+
+impl DirSizes {
+    /// Whether every directory was read.
+    pub fn is_complete(&self) -> bool {
+        self.stopped.is_none()
+    }
+
+    /// A cache entry for this result, as a scan of `base` with `options`.
+    pub fn to_cache_entry(&self, base: &Path, options: ScanOptions) -> CacheEntry {
+        let mut entry = CacheEntry::new(base, self.sizes.clone(), self.total_files, options)
+            .with_hardlinks(&self.hardlinks)
+            .with_hardlink_dirs(&self.hardlink_dirs)
+            .with_file_counts(self.file_counts.clone())
+            .with_modified(self.modified.clone());
+        entry.hardlink_stats = self.hardlink_stats;
+        entry.symlinks = self.symlinks;
+        entry
+    }
+}
 
-            // No cache hit, compute normally and store in cache
-            let (sizes, total_files, duration) =
-                compute_dir_sizes_with_progress_internal(base, quiet);
-
-            // Store in cache
-            if let Err(e) = cache.store(base, &sizes, total_files) {
-                if !quiet {
-                    eprintln!("⚠️  Warning: Failed to store cache: {e}");
-                }
-            } else if !quiet {
-                eprintln!(
-                    "💾 {} {}",
-                    "Cached results for".bright_blue(),
-                    base.display().to_string().bright_white()
-                );
+impl From<ScanResult> for DirSizes {
+    fn from(result: ScanResult) -> Self {
+        let total_files = result.total_files();
+        let mut sizes = result.tree.to_size_map();
+        let mut file_counts = result.tree.to_file_count_map();
+        let mut modified = result.tree.to_modified_map();
+        let mut compressed = HashMap::new();
+        // Archive contents are listed alongside the directories that hold them
+        for archive in result.archives {
+            for (path, bytes) in archive.tree.to_size_map() {
+                sizes.entry(path).or_insert(bytes);
+            }
+            for (path, files) in archive.tree.to_file_count_map() {
+                file_counts.entry(path).or_insert(files);
+            }
+            for (path, time) in archive.tree.to_modified_map() {
+                modified.entry(path).or_insert(time);
             }
+            compressed.extend(archive.compressed);
+        }
 
-            (sizes, total_files, duration)
-        } else {
-            // Cache creation failed, fall back to normal computation
-            compute_dir_sizes_with_progress_internal(base, quiet)
+        DirSizes {
+            total_files,
+            sizes,
+            file_counts,
+            modified,
+            duration: result.duration,
+            stopped: result.stopped,
+            hardlinks: result.hardlinks,
+            hardlink_dirs: result.hardlink_dirs,
+            hardlink_stats: result.hardlink_stats,
+            symlinks: result.symlinks,
+            errors: result.errors,
+            compressed,
+            cache: CacheStatus::Disabled,
         }
-    } else {
-        compute_dir_sizes_with_progress_internal(base, quiet)
     }
 }
 
-pub fn compute_dir_sizes_with_progress(
+impl From<CacheEntry> for DirSizes {
+    fn from(entry: CacheEntry) -> Self {
+        DirSizes {
+            hardlinks: entry.hardlink_map(),
+            hardlink_dirs: entry.hardlink_dirs_below(&entry.base_path),
+            hardlink_stats: entry.hardlink_stats,
+            symlinks: entry.symlinks,
+            sizes: entry.sizes,
+            file_counts: entry.file_counts,
+            modified: entry.modified,
+            total_files: entry.total_files,
+            duration: Duration::from_secs(0),
+            stopped: None,
+            errors: 0,
+            compressed: HashMap::new(),
+            cache: CacheStatus::Hit,
+        }
+    }
+}
+
+/// Compute directory sizes with caching support
+pub fn compute_dir_sizes_cached(
     base: &Path,
-    quiet: bool,
+    use_cache: bool,
+    max_cache_age_hours: u64,
 ) -> (HashMap<PathBuf, u64>, usize, Duration) {
-    compute_dir_sizes_with_progress_internal(base, quiet)
+    let cache = use_cache.then(Cache::new).and_then(Result::ok);
+    let result = scan_with_cache(&Scanner::new(base), cache.as_ref(), max_cache_age_hours);
+    (result.sizes, result.total_files, result.duration)
 }
 
-fn compute_dir_sizes_with_progress_internal(
+/// Compute directory sizes with caching support.
+///
+/// `quiet` is ignored: the library no longer prints cache messages, and
+/// callers can inspect [`CacheStatus`] through [`scan_with_cache`] instead.
+#[deprecated(
+    since = "0.2.7",
+    note = "the `quiet` flag has no effect; use `compute_dir_sizes_cached` instead"
+)]
+pub fn compute_dir_sizes_with_cache(
     base: &Path,
     _quiet: bool,
+    use_cache: bool,
+    max_cache_age_hours: u64,
 ) -> (HashMap<PathBuf, u64>, usize, Duration) {
-    let start_time = Instant::now();
-
-    // Use DashMap for thread-safe concurrent access with pre-allocated capacity
-    let estimated_dirs = 1000; // Reasonable estimate for most directories
-    let sizes = Arc::new(DashMap::with_capacity(estimated_dirs));
-    #[cfg(all(unix, not(test)))]
-    let seen_inodes = Arc::new(DashMap::<(u64, u64), bool>::with_capacity(
-        estimated_dirs / 10,
-    )); // Fewer hardlinks expected
-    let file_count = Arc::new(AtomicUsize::new(0));
-
-    // Create a channel for streaming file processing
-    // Use a larger buffer for better throughput on fast storage
-    let (tx, rx) = bounded(num_cpus::get() * 500); // Buffer based on CPU count
-    let base_path = base.to_path_buf();
-
-    // Spawn a thread to walk the directory and send files to the channel
-    let walker_thread = {
-        let tx = tx.clone();
-        let base_path = base_path.clone();
-
-        thread::spawn(move || {
-            let mut count = 0;
-            for entry in WalkDir::new(&base_path)
-                .follow_links(false)
-                .into_iter()
-                .filter_map(Result::ok)
-                .filter(is_file)
-            {
-                count += 1;
+    compute_dir_sizes_cached(base, use_cache, max_cache_age_hours)
+}
 
-                if tx.send(entry).is_err() {
-                    break; // Receiver dropped
-                }
+/// Run `scanner` with caching support, reusing cached results for its base
+/// directory (or a parent of it) in `cache` when they are recent enough.
+///
+/// Without a cache this is a plain scan. Scans that were cancelled or timed
+/// out are returned as-is but never written to the cache. Nothing is
+/// printed; how the cache was used, including any cache error, is reported
+/// in [`DirSizes::cache`].
+pub fn scan_with_cache(
+    scanner: &Scanner,
+    cache: Option<&Cache>,
+    max_cache_age_hours: u64,
+) -> DirSizes {
+    let cache = match cache {
+        Some(cache) => cache,
+        None => return scanner.scan().into(),
+    };
+
+    let base = scanner.base();
+    let max_age = max_cache_age_hours * 3600;
+    let options = scanner.options();
+    match cache.retrieve_with_options(base, &options, max_age) {
+        Ok(Some(cached_entry)) => return cached_entry.into(),
+        Ok(None) => {}
+        // A corrupt entry is replaced by the fresh scan below
+        Err(Error::CorruptCache { .. }) => {}
+        Err(e) => {
+            let mut result = DirSizes::from(scanner.scan());
+            result.cache = CacheStatus::Failed(e);
+            return result;
+        }
+    }
+
+    // Check if we can use a parent directory's cache for this subdirectory
+    if let Some(parent) = base.parent() {
+        if let Ok(Some(parent_cache)) = cache.retrieve_with_options(parent, &options, max_age) {
+            if let Some((filtered_sizes, file_count)) =
+                cache.can_use_for_subdir(&parent_cache, base)
+            {
+                // Only the links inside this directory count towards it
+                let hardlink_dirs = parent_cache.hardlink_dirs_below(base);
+                let file_counts = parent_cache
+                    .file_counts
+                    .into_iter()
+                    .filter(|(path, _)| path.starts_with(base))
+                    .collect();
+                let modified = parent_cache
+                    .modified
+                    .into_iter()
+                    .filter(|(path, _)| path.starts_with(base))
+                    .collect();
+                let hardlinks = hardlink_dirs
+                    .iter()
+                    .map(|(&inode, dirs)| (inode, dirs.iter().map(|(_, bytes)| bytes).sum()))
+                    .collect();
+                return DirSizes {
+                    sizes: filtered_sizes,
+                    file_counts,
+                    modified,
+                    total_files: file_count,
+                    duration: Duration::from_secs(0),
+                    stopped: None,
+                    hardlinks,
+                    hardlink_dirs,
+                    hardlink_stats: HardlinkStats::default(),
+                    symlinks: SymlinkStats::default(),
+                    errors: 0,
+                    compressed: HashMap::new(),
+                    cache: CacheStatus::ParentHit,
+                };
             }
+        }
+    }
 
-            count
-        })
+    // No cache hit: walk the directory, reusing cached scans of
+    // subdirectories, and store the result
+    let children = cache
+        .retrieve_below_with_options(base, &options, max_age)
+        .unwrap_or_default();
+    let (mut result, reused) = scan_reusing(scanner, children);
+    result.cache = if !result.is_complete() {
+        CacheStatus::Incomplete
+    } else {
+        match cache.store_entry(&result.to_cache_entry(base, options)) {
+            Ok(()) if reused > 0 => CacheStatus::Composed(reused),
+            Ok(()) => CacheStatus::Stored,
+            Err(e) => CacheStatus::Failed(e),
+        }
     };
+    result
+}
 
-    // Process files in parallel using a thread pool
-
-    // Spawn worker threads to process files from the channel
-    let num_workers = num_cpus::get().min(8); // Limit to 8 threads max
-    let mut worker_handles = Vec::with_capacity(num_workers);
-
-    for _ in 0..num_workers {
-        let rx = rx.clone();
-        let sizes = Arc::clone(&sizes);
-        #[cfg(all(unix, not(test)))]
-        let seen_inodes = Arc::clone(&seen_inodes);
-        let file_count = Arc::clone(&file_count);
-
-        let base_path = base_path.clone();
-
-        let handle = thread::spawn(move || {
-            while let Ok(entry) = rx.recv() {
-                if let Ok(metadata) = entry.metadata() {
-                    #[cfg(all(unix, not(test)))]
-                    let mut file_size = metadata.len();
-                    #[cfg(any(not(unix), test))]
-                    let file_size = metadata.len();
-                    file_count.fetch_add(1, Ordering::Relaxed);
-
-                    // Check for hardlinks to avoid double-counting (Unix only)
-                    #[cfg(all(unix, not(test)))]
-                    {
-                        use std::os::unix::fs::MetadataExt;
-                        let inode = metadata.ino();
-                        let device = metadata.dev();
-                        let key = (device, inode);
-
-                        // If this inode has multiple links, only count it once
-                        if metadata.nlink() > 1 && seen_inodes.insert(key, true).is_some() {
-                            // Skip this file, we've already counted it
-                            file_size = 0;
-                        }
-                    }
+/// Scan with `scanner`, taking the sizes of subdirectories from `children`,
+/// cached scans made with the same options, instead of walking them.
+///
+/// Returns the combined result and how many cached scans it includes.
+/// Cached directories that no longer exist are ignored. If the walk meets an
+/// inode that a cached scan counted as well, the directory is walked in full
+/// instead, so that hardlinks are attributed and reported exactly as in a
+/// fresh scan. Proportional hardlink shares depend on every link in the tree,
+/// and followed symlinks may lead to the same targets from inside and outside
+/// a cached scan, so such scans are never composed.
+fn scan_reusing(scanner: &Scanner, mut children: Vec<CacheEntry>) -> (DirSizes, usize) {
+    let options = scanner.options();
+    children.retain(|child| child.options == options);
+    if children.is_empty()
+        || options.hardlinks == HardlinkPolicy::Proportional
+        || options.follow_symlinks
+    {
+        return (scanner.scan().into(), 0);
+    }
 
-                    // Only process if we have a size to add
-                    if file_size > 0 {
-                        let mut current = entry.path();
-
-                        // Bubble up file size to each ancestor directory
-                        while let Some(parent) = current.parent() {
-                            if !parent.starts_with(&base_path) {
-                                break;
-                            }
-                            // Use DashMap's entry API for atomic updates
-                            // Clone PathBuf only when necessary (when inserting new entry)
-                            match sizes.get_mut(parent) {
-                                Some(mut existing_size) => {
-                                    *existing_size += file_size;
-                                }
-                                None => {
-                                    sizes.insert(parent.to_path_buf(), file_size);
-                                }
-                            }
-                            current = parent;
-                        }
-                    }
-                }
-            }
-        });
+    // Parents sort before their subdirectories, so the outermost scans win
+    children.sort_by(|a, b| a.base_path.cmp(&b.base_path));
+    let mut chosen: Vec<CacheEntry> = Vec::new();
+    let mut linked: HashMap<(u64, u64), PathBuf> = HashMap::new();
+    for child in children {
+        if chosen
+            .iter()
+            .any(|other| child.base_path.starts_with(&other.base_path))
+        {
+            continue;
+        }
+        // There is no telling which directories inside a cached scan hold an
+        // inode, so scans sharing one cannot be combined; walk the later one
+        let links = child.hardlink_map();
+        if links.keys().any(|key| linked.contains_key(key)) {
+            continue;
+        }
+        linked.extend(links.into_keys().map(|key| (key, child.base_path.clone())));
+        chosen.push(child);
+    }
 
-        worker_handles.push(handle);
+    let skip = SkipCached {
+        cached: chosen.iter().map(|child| child.base_path.clone()).collect(),
+        linked: &linked,
+        reached: Mutex::new(HashSet::new()),
+    };
+    let (scan, shared) = scanner.scan_with(&skip);
+    let reached = skip.reached.into_inner().unwrap_or_else(|e| e.into_inner());
+    if shared.iter().any(|child| reached.contains(child)) {
+        return (scanner.scan().into(), 0);
     }
 
-    // Drop the sender to signal completion
-    drop(tx);
+    let base = scanner.base();
+    let mut result = DirSizes::from(scan);
+    let mut reused = 0;
+    for child in chosen {
+        if !reached.contains(&child.base_path) {
+            continue;
+        }
+        let bytes = child.sizes.get(&child.base_path).copied().unwrap_or(0);
+        let newest = child.modified.get(&child.base_path).copied().unwrap_or(0);
+        for ancestor in ancestors_within(&child.base_path, base) {
+            *result.sizes.entry(ancestor.to_path_buf()).or_insert(0) += bytes;
+            *result
+                .file_counts
+                .entry(ancestor.to_path_buf())
+                .or_insert(0) += child.total_files as u64;
+            let modified = result.modified.entry(ancestor.to_path_buf()).or_insert(0);
+            *modified = (*modified).max(newest);
+        }
+        result.total_files += child.total_files;
+        result.hardlinks.extend(child.hardlink_map());
+        result
+            .hardlink_dirs
+            .extend(child.hardlink_dirs_below(&child.base_path));
+        result.hardlink_stats.merge(&child.hardlink_stats);
+        result.symlinks.merge(&child.symlinks);
+        result.sizes.extend(child.sizes);
+        result.file_counts.extend(child.file_counts);
+        result.modified.extend(child.modified);
+        reused += 1;
+    }
+    (result, reused)
+}
+
+/// The ancestors of `path`, excluding `path` itself, up to and including `base`.
+fn ancestors_within<'p>(path: &'p Path, base: &'p Path) -> impl Iterator<Item = &'p Path> {
+    path.ancestors()
+        .skip(1)
+        .take_while(move |ancestor| ancestor.starts_with(base))
+}
+
+/// Skips directories covered by cached scans during a walk, and collects the
+/// cached scans that counted an inode the walk came across as well.
+struct SkipCached<'a> {
+    /// Base paths of the cached scans
+    cached: HashSet<PathBuf>,
+    /// Multiply-linked inodes counted by the cached scans, with the scan's base path
+    linked: &'a HashMap<(u64, u64), PathBuf>,
+    /// Cached directories the walk came across
+    reached: Mutex<HashSet<PathBuf>>,
+}
+
+impl Aggregator for SkipCached<'_> {
+    type Output = HashSet<PathBuf>;
+
+    fn init(&self) -> Self::Output {
+        HashSet::new()
+    }
+
+    fn visit(&self, acc: &mut Self::Output, file: &FileEntry<'_>) {
+        if file.metadata.nlink < 2 {
+            return;
+        }
+        if let Some(child) = self.linked.get(&(file.metadata.dev, file.metadata.ino)) {
+            acc.insert(child.clone());
+        }
+    }
+
+    fn merge(&self, mut a: Self::Output, b: Self::Output) -> Self::Output {
+        a.extend(b);
+        a
+    }
+
+    fn enter_dir(&self, path: &Path) -> bool {
+        if !self.cached.contains(path) {
+            return true;
+        }
+        self.reached
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .insert(path.to_path_buf());
+        false
+    }
+}
 
-    // Wait for the walker thread to complete
-    let _total_files = walker_thread.join().unwrap_or(0);
+/// Results for several roots scanned together by [`scan_roots_with_cache`].
+pub struct MultiRootSizes {
+    /// Each root with its own results, in the order the scanners were given
+    pub roots: Vec<(PathBuf, DirSizes)>,
+    /// Combined size of all roots, counting overlapping roots and inodes
+    /// hardlinked from more than one root only once
+    pub total_bytes: u64,
+    /// Combined file count of all roots, counting overlapping roots once
+    pub total_files: usize,
+    /// Bytes that were not double counted because the same inode was
+    /// hardlinked from more than one root
+    pub shared_bytes: u64,
+    /// Wall-clock time for the whole multi-root scan
+    pub duration: Duration,
+}
 
-    // Wait for all worker threads to complete
-    for handle in worker_handles {
-        let _ = handle.join();
+impl MultiRootSizes {
+    /// Wrap the results of a single root.
+    pub fn single(root: PathBuf, sizes: DirSizes) -> Self {
+        MultiRootSizes {
+            total_bytes: sizes.sizes.get(&root).copied().unwrap_or(0),
+            total_files: sizes.total_files,
+            shared_bytes: 0,
+            duration: sizes.duration,
+            roots: vec![(root, sizes)],
+        }
     }
 
-    // Convert DashMap to HashMap for return
-    let final_sizes: HashMap<PathBuf, u64> = Arc::try_unwrap(sizes)
-        .unwrap_or_else(|_| panic!("Failed to unwrap Arc"))
-        .into_iter()
+    /// Why the first incomplete root stopped early, or `None` if all completed.
+    pub fn stopped(&self) -> Option<StopReason> {
+        self.roots.iter().find_map(|(_, sizes)| sizes.stopped)
+    }
+}
+
+/// Scan several roots concurrently, caching each one independently.
+///
+/// Each root is scanned (or served from cache) exactly as
+/// [`scan_with_cache`] would on its own, with all scans sharing one thread
+/// pool. The grand total skips roots nested inside another root and
+/// subtracts inodes hardlinked from several roots. Roots should be canonical
+/// paths so that nesting can be detected.
+///
+/// Fails with [`Error::ScanFailed`] if a scan panicked.
+pub fn scan_roots_with_cache(
+    scanners: &[Scanner],
+    cache: Option<&Cache>,
+    max_cache_age_hours: u64,
+) -> Result<MultiRootSizes> {
+    scan_roots(scanners, |scanner| {
+        scan_with_cache(scanner, cache, max_cache_age_hours)
+    })
+}
+
+/// Scan several roots concurrently without using cached results, then store
+/// each complete scan in `cache`, replacing any earlier scan of that root.
+///
+/// Totals are combined, and failures reported, as in [`scan_roots_with_cache`].
+pub fn rescan_roots_with_cache(scanners: &[Scanner], cache: &Cache) -> Result<MultiRootSizes> {
+    scan_roots(scanners, |scanner| {
+        let mut result = DirSizes::from(scanner.scan());
+        result.cache = if !result.is_complete() {
+            CacheStatus::Incomplete
+        } else {
+            match cache.store_entry(&result.to_cache_entry(scanner.base(), scanner.options())) {
+                Ok(()) => CacheStatus::Stored,
+                Err(e) => CacheStatus::Failed(e),
+            }
+        };
+        result
+    })
+}
+
+/// Run `scan` for every scanner on its own thread and combine the totals.
+///
+/// The threads only wait for their walks, which all run on the shared scan
+/// pool.
+fn scan_roots(
+    scanners: &[Scanner],
+    scan: impl Fn(&Scanner) -> DirSizes + Sync,
+) -> Result<MultiRootSizes> {
+    let start_time = std::time::Instant::now();
+    let results: Vec<DirSizes> = std::thread::scope(|s| {
+        let scan = &scan;
+        let handles: Vec<_> = scanners
+            .iter()
+            .map(|scanner| s.spawn(move || scan(scanner)))
+            .collect();
+        handles
+            .into_iter()
+            .map(|handle| handle.join().map_err(|_| Error::ScanFailed))
+            .collect::<Result<_>>()
+    })?;
+
+    let roots: Vec<(PathBuf, DirSizes)> = scanners
+        .iter()
+        .map(|scanner| scanner.base().to_path_buf())
+        .zip(results)
         .collect();
-    let final_file_count = file_count.load(Ordering::Relaxed);
 
-    // Ensure the base directory is present even if empty
-    let mut result_sizes = final_sizes;
-    result_sizes.entry(base.to_path_buf()).or_insert(0);
+    // Only roots that are not inside another root contribute to the totals
+    let mut total_bytes = 0;
+    let mut total_files = 0;
+    // Bytes the roots counted for each inode, summed and the largest
+    let mut inode_roots: HashMap<(u64, u64), (u64, u64)> = HashMap::new();
+    for (index, (root, sizes)) in roots.iter().enumerate() {
+        let nested = roots.iter().enumerate().any(|(other, (other_root, _))| {
+            other != index && root.starts_with(other_root) && (root != other_root || other < index)
+        });
+        if nested {
+            continue;
+        }
+
+        total_bytes += sizes.sizes.get(root).copied().unwrap_or(0);
+        total_files += sizes.total_files;
+        for (&inode, &bytes) in &sizes.hardlinks {
+            let (sum, max) = inode_roots.entry(inode).or_insert((0, 0));
+            *sum += bytes;
+            *max = (*max).max(bytes);
+        }
+    }
 
-    let duration = start_time.elapsed();
-    (result_sizes, final_file_count, duration)
+    // Each inode is kept once, in the root that counted the most of it
+    let shared_bytes: u64 = inode_roots.values().map(|&(sum, max)| sum - max).sum();
+
+    Ok(MultiRootSizes {
+        roots,
+        total_bytes: total_bytes.saturating_sub(shared_bytes),
+        total_files,
+        shared_bytes,
+        duration: start_time.elapsed(),
+    })
+}
+
+/// Compute directory sizes, returning the size map, total file count and scan time.
+///
+/// `on_progress` is called periodically with a [`ScanProgress`] snapshot
+/// while the scan runs. To draw a live progress line on stderr, forward the
+/// snapshots to a [`ProgressDisplay`].
+pub fn compute_dir_sizes_with_callback(
+    base: &Path,
+    on_progress: impl Fn(&ScanProgress) + Send + Sync,
+) -> (HashMap<PathBuf, u64>, usize, Duration) {
+    let result = DirSizes::from(Scanner::new(base).on_progress(on_progress).scan());
+    (result.sizes, result.total_files, result.duration)
+}
+
+/// Compute directory sizes, returning the size map, total file count and scan time.
+///
+/// `quiet` is ignored; nothing is printed either way.
+#[deprecated(
+    since = "0.2.7",
+    note = "the `quiet` flag has no effect; use `compute_dir_sizes_with_callback` instead"
+)]
+pub fn compute_dir_sizes_with_progress(
+    base: &Path,
+    _quiet: bool,
+) -> (HashMap<PathBuf, u64>, usize, Duration) {
+    compute_dir_sizes_with_callback(base, |_| {})
+}
+
+/// Traverse `base` recursively and return its directories as an arena-backed [`DirTree`].
+///
+/// This is the memory-efficient form of [`compute_dir_sizes`]: each directory
+/// is stored once with an interned name instead of as an owned path, and
+/// subtree totals are already aggregated.
+///
+/// # Example
+///
+/// ```rust
+/// use std::path::Path;
+/// use rudu::compute_dir_tree;
+/// let tree = compute_dir_tree(Path::new("/tmp/mydir"));
+/// for id in tree.children(tree.root()) {
+///     println!("{}: {} bytes", tree.path(id).display(), tree.size(id));
+/// }
+/// ```
+pub fn compute_dir_tree(base: &Path) -> DirTree {
+    Scanner::new(base).scan().tree
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::filesystem::{FileSystem, MemoryFs};
     use std::fs;
     use tempfile::tempdir;
 
@@ -325,12 +693,12 @@ mod tests {
         std::fs::write(file_path, "hello world").unwrap(); // 11 bytes
 
         // First scan without cache
-        let (sizes1, files1, _) = compute_dir_sizes_with_cache(dir.path(), true, false, 24);
+        let (sizes1, files1, _) = compute_dir_sizes_cached(dir.path(), false, 24);
         assert_eq!(sizes1.get(dir.path()), Some(&11));
         assert_eq!(files1, 1);
 
         // Second scan with cache enabled - should produce same results
-        let (sizes2, files2, _) = compute_dir_sizes_with_cache(dir.path(), true, true, 24);
+        let (sizes2, files2, _) = compute_dir_sizes_cached(dir.path(), true, 24);
         assert_eq!(sizes2.get(dir.path()), Some(&11));
         assert_eq!(files2, 1);
 
@@ -339,13 +707,98 @@ mod tests {
         assert_eq!(files1, files2);
     }
 
+    #[test]
+    #[allow(deprecated)]
+    fn test_deprecated_cache_wrapper_ignores_quiet() {
+        let dir = tempdir().unwrap();
+        std::fs::write(dir.path().join("test.txt"), "hello").unwrap();
+
+        let (sizes, files, _) = compute_dir_sizes_with_cache(dir.path(), true, false, 24);
+        assert_eq!(sizes, compute_dir_sizes_cached(dir.path(), false, 24).0);
+        assert_eq!(files, 1);
+    }
+
+    #[test]
+    #[allow(deprecated)]
+    fn test_deprecated_progress_wrapper_ignores_quiet() {
+        let dir = tempdir().unwrap();
+        std::fs::write(dir.path().join("test.txt"), "hello").unwrap();
+
+        let (sizes, files, _) = compute_dir_sizes_with_progress(dir.path(), false);
+        assert_eq!(sizes, compute_dir_sizes(dir.path()));
+        assert_eq!(files, 1);
+    }
+
+    #[test]
+    fn test_scan_roots_skips_nested_roots() {
+        let dir = tempdir().unwrap();
+        let a = dir.path().join("a");
+        let b = dir.path().join("b");
+        let nested = a.join("nested");
+        fs::create_dir_all(&nested).unwrap();
+        fs::create_dir_all(&b).unwrap();
+        fs::write(a.join("one.txt"), "12345").unwrap(); // 5 bytes
+        fs::write(nested.join("two.txt"), "123").unwrap(); // 3 bytes
+        fs::write(b.join("three.txt"), "12").unwrap(); // 2 bytes
+
+        let scanners = [Scanner::new(&a), Scanner::new(&nested), Scanner::new(&b)];
+        let result = scan_roots_with_cache(&scanners, None, 24).unwrap();
+
+        assert_eq!(result.roots.len(), 3);
+        assert_eq!(result.roots[0].1.sizes.get(&a), Some(&8));
+        assert_eq!(result.roots[1].1.sizes.get(&nested), Some(&3));
+        assert_eq!(result.roots[2].1.sizes.get(&b), Some(&2));
+        // The nested root is already included in `a`
+        assert_eq!(result.total_bytes, 10);
+        assert_eq!(result.total_files, 3);
+        assert!(result.stopped().is_none());
+    }
+
+    #[test]
+    fn test_scan_roots_dedups_links_in_parent_cache_hits() {
+        fn linked() -> MemoryFs {
+            let mut memory = MemoryFs::new();
+            memory.add_file("/data/a/file", 100);
+            memory.add_file("/data/b/own", 1);
+            memory.add_hardlink("/other/link", "/data/a/file");
+            memory
+        }
+        let cache_dir = tempdir().unwrap();
+        let cache = Cache::with_dir(cache_dir.path()).unwrap();
+        scan_with_cache(&Scanner::new("/data").filesystem(linked()), Some(&cache), 1);
+
+        let scanners = [
+            Scanner::new("/data/a"),
+            Scanner::new("/other").filesystem(linked()),
+        ];
+        let result = scan_roots_with_cache(&scanners, Some(&cache), 1).unwrap();
+
+        assert!(matches!(result.roots[0].1.cache, CacheStatus::ParentHit));
+        assert_eq!(result.roots[0].1.hardlinks.len(), 1);
+        assert_eq!(result.shared_bytes, 100);
+        assert_eq!(result.total_bytes, 100);
+    }
+
+    #[test]
+    fn test_scan_roots_reports_a_panicked_scan() {
+        let mut memory = MemoryFs::new();
+        memory.add_file("/m/locked/file", 1);
+        memory.deny("/m/locked");
+        let scanners = [Scanner::new("/m")
+            .filesystem(memory)
+            .on_error(|_| panic!("error callback panicked"))];
+
+        let result = scan_roots_with_cache(&scanners, None, 24);
+        assert!(matches!(result, Err(Error::ScanFailed)));
+    }
+
     #[test]
     fn test_cache_store_and_retrieve() {
         let dir = tempdir().unwrap();
         let cache_dir = tempdir().unwrap();
 
         // Create a temporary cache in the test directory
-        let cache = Cache::with_dir(cache_dir.path().to_path_buf()).unwrap();
+        let cache = Cache::with_dir(cache_dir.path()).unwrap();
 
         let mut test_sizes = HashMap::new();
         test_sizes.insert(dir.path().to_path_buf(), 100);
@@ -363,12 +816,324 @@ mod tests {
         assert_eq!(entry.base_path, dir.path());
     }
 
+    #[test]
+    fn test_corrupt_cache_entry_is_reported() {
+        let dir = tempdir().unwrap();
+        let cache_dir = tempdir().unwrap();
+        let cache = Cache::with_dir(cache_dir.path()).unwrap();
+        cache.store(dir.path(), &HashMap::new(), 0).unwrap();
+
+        for entry in fs::read_dir(cache_dir.path()).unwrap() {
+            let path = entry.unwrap().path();
+            if path.extension() == Some("json".as_ref()) {
+                fs::write(path, "{ not json").unwrap();
+            }
+        }
+
+        let err = cache.retrieve(dir.path(), 3600).unwrap_err();
+        assert!(matches!(err, Error::CorruptCache { .. }));
+        assert!(err.to_string().starts_with("corrupt cache file"));
+
+        // The entry is moved aside, so the next lookup is a plain miss
+        let quarantined = fs::read_dir(cache_dir.path().join("quarantine")).unwrap();
+        assert_eq!(quarantined.count(), 1);
+        assert!(cache.retrieve(dir.path(), 3600).unwrap().is_none());
+        assert_eq!(cache.stats().unwrap().0, 0);
+    }
+
+    #[test]
+    fn test_concurrent_cache_writes() {
+        let dir = tempdir().unwrap();
+        let cache_dir = tempdir().unwrap();
+        let cache = Cache::with_dir(cache_dir.path()).unwrap();
+
+        std::thread::scope(|scope| {
+            for writer in 0..8usize {
+                let cache = &cache;
+                let path = dir.path();
+                scope.spawn(move || {
+                    let sizes: HashMap<_, _> = (0..200)
+                        .map(|i| (path.join(format!("d{i}")), writer as u64))
+                        .collect();
+                    for _ in 0..10 {
+                        cache.store(path, &sizes, writer).unwrap();
+                        // Readers never observe a partially written entry
+                        assert!(cache.retrieve(path, 3600).unwrap().is_some());
+                    }
+                });
+            }
+        });
+
+        let entry = cache.retrieve(dir.path(), 3600).unwrap().unwrap();
+        assert_eq!(entry.sizes.len(), 200);
+        assert!(entry
+            .sizes
+            .values()
+            .all(|&size| size == entry.total_files as u64));
+        assert_eq!(cache.stats().unwrap().0, 1);
+    }
+
+    #[test]
+    fn test_cache_evicts_least_recently_used() {
+        let dirs: Vec<_> = (0..3).map(|_| tempdir().unwrap()).collect();
+        let cache_dir = tempdir().unwrap();
+        let cache = Cache::with_dir(cache_dir.path()).unwrap().max_entries(2);
+        // Keep modification times apart on filesystems with coarse timestamps
+        let tick = || std::thread::sleep(Duration::from_millis(50));
+
+        cache.store(dirs[0].path(), &HashMap::new(), 1).unwrap();
+        tick();
+        cache.store(dirs[1].path(), &HashMap::new(), 2).unwrap();
+        tick();
+        // Reading the first entry makes the second one least recently used
+        assert!(cache.retrieve(dirs[0].path(), 3600).unwrap().is_some());
+        tick();
+        cache.store(dirs[2].path(), &HashMap::new(), 3).unwrap();
+
+        assert_eq!(cache.stats().unwrap().0, 2);
+        assert!(cache.retrieve(dirs[0].path(), 3600).unwrap().is_some());
+        assert!(cache.retrieve(dirs[1].path(), 3600).unwrap().is_none());
+        assert!(cache.retrieve(dirs[2].path(), 3600).unwrap().is_some());
+
+        // The newest entry is kept even if it alone exceeds the size limit
+        let cache = cache.max_size(1);
+        cache.store(dirs[1].path(), &HashMap::new(), 4).unwrap();
+        assert_eq!(cache.stats().unwrap().0, 1);
+        assert!(cache.retrieve(dirs[1].path(), 3600).unwrap().is_some());
+    }
+
+    #[test]
+    fn test_cache_prune() {
+        let kept = tempdir().unwrap();
+        let removed = tempdir().unwrap();
+        let cache_dir = tempdir().unwrap();
+        let cache = Cache::with_dir(cache_dir.path()).unwrap();
+
+        cache.store(kept.path(), &HashMap::new(), 1).unwrap();
+        cache.store(removed.path(), &HashMap::new(), 1).unwrap();
+        fs::write(cache_dir.path().join("garbage.json"), "{ not json").unwrap();
+        let orphan = removed.path().to_path_buf();
+        drop(removed);
+
+        let stats = cache.prune(3600).unwrap();
+        assert_eq!(
+            (stats.expired, stats.orphaned, stats.corrupt, stats.evicted),
+            (0, 1, 1, 0)
+        );
+        assert!(stats.freed_bytes > 0);
+        assert_eq!(cache.stats().unwrap().0, 1);
+        assert!(cache.retrieve(kept.path(), 3600).unwrap().is_some());
+        assert!(cache.retrieve(&orphan, 3600).unwrap().is_none());
+
+        // Pruning also enforces the limits
+        let stats = cache.max_entries(0).prune(3600).unwrap();
+        assert_eq!((stats.evicted, stats.removed()), (1, 1));
+    }
+
+    #[test]
+    fn test_cache_list_and_invalidate() {
+        let root = tempdir().unwrap();
+        let (a, b) = (root.path().join("a"), root.path().join("a/b"));
+        let other = tempdir().unwrap();
+        let cache_dir = tempdir().unwrap();
+        let cache = Cache::with_dir(cache_dir.path()).unwrap();
+
+        let scanner = Scanner::new(&b).archives(true);
+        let options = scanner.options();
+        cache.store(&a, &HashMap::new(), 3).unwrap();
+        cache
+            .store_with_options(&b, &HashMap::new(), 2, &HashMap::new(), &options)
+            .unwrap();
+        cache.store(other.path(), &HashMap::new(), 1).unwrap();
+
+        let listed = cache.list().unwrap();
+        assert_eq!(listed.len(), 3);
+        let entry_b = listed.iter().find(|entry| entry.base_path == b).unwrap();
+        assert_eq!((entry_b.total_files, entry_b.options), (2, options));
+        assert_eq!(entry_b.options.to_string(), "archives");
+        assert!(entry_b.size > 0 && entry_b.age() < Duration::from_secs(60));
+        assert_eq!(
+            cache.get(&a).unwrap().unwrap().options.to_string(),
+            "defaults"
+        );
+
+        assert!(cache.invalidate(other.path()).unwrap());
+        assert!(!cache.invalidate(other.path()).unwrap());
+        assert_eq!(cache.invalidate_under(root.path()).unwrap(), 2);
+        assert!(cache.list().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_parent_scan_reuses_cached_children() {
+        let mut memory = MemoryFs::new();
+        memory.add_file("/data/a/one", 100);
+        memory.add_file("/data/b/two", 50);
+        memory.add_file("/data/c/three", 10);
+        let cache_dir = tempdir().unwrap();
+        let cache = Cache::with_dir(cache_dir.path()).unwrap();
+
+        // Distinct cached sizes show where the results came from
+        let a = PathBuf::from("/data/a");
+        let cached_a = HashMap::from([(a.clone(), 1_000), (a.join("x"), 400)]);
+        cache.store(&a, &cached_a, 7).unwrap();
+        // Made with other options, so /data/b is walked
+        let b = PathBuf::from("/data/b");
+        let archives = Scanner::new(&b).archives(true).options();
+        cache
+            .store_with_options(
+                &b,
+                &HashMap::from([(b.clone(), 5)]),
+                1,
+                &HashMap::new(),
+                &archives,
+            )
+            .unwrap();
+        // No longer on disk, so ignored
+        let gone = PathBuf::from("/data/gone");
+        cache
+            .store(&gone, &HashMap::from([(gone.clone(), 999)]), 1)
+            .unwrap();
+
+        let scanner = Scanner::new("/data").filesystem(memory);
+        let result = scan_with_cache(&scanner, Some(&cache), 1);
+
+        assert!(matches!(result.cache, CacheStatus::Composed(1)));
+        assert_eq!(result.sizes[Path::new("/data")], 1_060);
+        assert_eq!(result.sizes[Path::new("/data/a/x")], 400);
+        assert_eq!(result.sizes[Path::new("/data/b")], 50);
+        assert!(!result.sizes.contains_key(&gone));
+        assert_eq!(result.total_files, 9);
+        // Cached scans add their file count to the directories above them
+        assert_eq!(result.file_counts[Path::new("/data")], 9);
+        assert_eq!(result.file_counts[Path::new("/data/b")], 1);
+
+        // The combined result is cached for the parent
+        let stored = cache.retrieve(Path::new("/data"), 3600).unwrap().unwrap();
+        assert_eq!(stored.sizes[Path::new("/data")], 1_060);
+        assert_eq!(stored.file_counts[Path::new("/data")], 9);
+    }
+
+    #[test]
+    fn test_modified_times_are_kept_with_the_cache() {
+        let mut memory = MemoryFs::new();
+        memory.add_file("/data/a/one", 100);
+        memory.add_file("/data/a/deep/two", 50);
+        memory.add_file("/data/b/three", 10);
+        memory.set_modified("/data/a/one", 1_000);
+        memory.set_modified("/data/a/deep/two", 3_000);
+        memory.set_modified("/data/b/three", 2_000);
+        let cache_dir = tempdir().unwrap();
+        let cache = Cache::with_dir(cache_dir.path()).unwrap();
+
+        let scanner = Scanner::new("/data").filesystem(memory);
+        let result = scan_with_cache(&scanner, Some(&cache), 1);
+        assert_eq!(result.modified[Path::new("/data")], 3_000);
+        assert_eq!(result.modified[Path::new("/data/a")], 3_000);
+        assert_eq!(result.modified[Path::new("/data/b")], 2_000);
+
+        let result = scan_with_cache(&scanner, Some(&cache), 1);
+        assert!(matches!(result.cache, CacheStatus::Hit));
+        assert_eq!(result.modified[Path::new("/data/a/deep")], 3_000);
+
+        let result = scan_with_cache(&Scanner::new("/data/b"), Some(&cache), 1);
+        assert!(matches!(result.cache, CacheStatus::ParentHit));
+        assert_eq!(
+            result.modified,
+            HashMap::from([(PathBuf::from("/data/b"), 2_000)])
+        );
+    }
+
+    #[test]
+    fn test_rescan_replaces_the_cached_scan() {
+        let dir = tempdir().unwrap();
+        let cache_dir = tempdir().unwrap();
+        let cache = Cache::with_dir(cache_dir.path()).unwrap();
+        fs::write(dir.path().join("one.txt"), "12345").unwrap();
+        let scanners = [Scanner::new(dir.path())];
+        scan_roots_with_cache(&scanners, Some(&cache), 24).unwrap();
+
+        fs::write(dir.path().join("two.txt"), "123").unwrap();
+        let cached = scan_roots_with_cache(&scanners, Some(&cache), 24).unwrap();
+        assert!(matches!(cached.roots[0].1.cache, CacheStatus::Hit));
+        assert_eq!(cached.total_bytes, 5);
+
+        let fresh = rescan_roots_with_cache(&scanners, &cache).unwrap();
+        assert!(matches!(fresh.roots[0].1.cache, CacheStatus::Stored));
+        assert_eq!((fresh.total_bytes, fresh.total_files), (8, 2));
+        let stored = cache
+            .get_with_options(dir.path(), &scanners[0].options())
+            .unwrap()
+            .unwrap();
+        assert_eq!(stored.sizes[dir.path()], 8);
+        assert_eq!(stored.file_counts[dir.path()], 2);
+    }
+
+    #[test]
+    fn test_parent_cache_hit_keeps_file_counts() {
+        let mut memory = MemoryFs::new();
+        memory.add_file("/data/a/one", 100);
+        memory.add_file("/data/a/deep/two", 50);
+        memory.add_file("/data/b/three", 10);
+        let cache_dir = tempdir().unwrap();
+        let cache = Cache::with_dir(cache_dir.path()).unwrap();
+
+        let scanner = Scanner::new("/data").filesystem(memory);
+        let result = scan_with_cache(&scanner, Some(&cache), 1);
+        assert_eq!(result.file_counts[Path::new("/data")], 3);
+        assert_eq!(result.file_counts[Path::new("/data/a/deep")], 1);
+
+        let result = scan_with_cache(&Scanner::new("/data/a"), Some(&cache), 1);
+        assert!(matches!(result.cache, CacheStatus::ParentHit));
+        assert_eq!(result.total_files, 2);
+        assert_eq!(
+            result.file_counts,
+            HashMap::from([
+                (PathBuf::from("/data/a"), 2),
+                (PathBuf::from("/data/a/deep"), 1),
+            ])
+        );
+    }
+
+    #[test]
+    fn test_composed_scan_matches_fresh_scan_across_hardlinks() {
+        let mut memory = MemoryFs::new();
+        memory.add_file("/data/b/file", 100);
+        // Sorts first, so a fresh scan counts the inode here
+        memory.add_hardlink("/data/a/link", "/data/b/file");
+        memory.add_file("/data/a/own", 1);
+        memory.add_file("/data/c/other", 10);
+        let file = memory.symlink_metadata(Path::new("/data/b/file")).unwrap();
+        let cache_dir = tempdir().unwrap();
+        let cache = Cache::with_dir(cache_dir.path()).unwrap();
+
+        // The cached scan of /data/b alone counted the inode there
+        let b = PathBuf::from("/data/b");
+        let hardlinks = HashMap::from([((file.dev, file.ino), 100)]);
+        cache
+            .store_with_hardlinks(&b, &HashMap::from([(b.clone(), 100)]), 1, &hardlinks)
+            .unwrap();
+
+        let scanner = Scanner::new("/data").filesystem(memory);
+        let fresh = DirSizes::from(scanner.scan());
+        let result = scan_with_cache(&scanner, Some(&cache), 1);
+
+        assert!(matches!(result.cache, CacheStatus::Stored));
+        assert_eq!(result.sizes, fresh.sizes);
+        assert_eq!(result.sizes[Path::new("/data/a")], 101);
+        assert_eq!(result.sizes[Path::new("/data/b")], 0);
+        assert_eq!(result.file_counts, fresh.file_counts);
+        assert_eq!(result.hardlinks, fresh.hardlinks);
+        assert_eq!(result.hardlink_stats, fresh.hardlink_stats);
+        let stored = cache.retrieve(Path::new("/data"), 3600).unwrap().unwrap();
+        assert_eq!(stored.sizes, fresh.sizes);
+    }
+
     #[test]
     fn test_cache_expiry() {
         let dir = tempdir().unwrap();
         let cache_dir = tempdir().unwrap();
 
-        let cache = Cache::with_dir(cache_dir.path().to_path_buf()).unwrap();
+        let cache = Cache::with_dir(cache_dir.path()).unwrap();
 
         let mut test_sizes = HashMap::new();
         test_sizes.insert(dir.path().to_path_buf(), 100);
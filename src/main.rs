@@ -1,25 +1,151 @@
 use clap::Parser;
 use colored::*;
-use humansize::{format_size, DECIMAL};
 use rayon::prelude::*;
-use std::path::PathBuf;
+use regex::Regex;
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::{self, BufReader};
+use std::path::{Path, PathBuf};
 use std::process;
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
-use rudu::{compute_dir_sizes_with_cache, Cache};
+use rudu::archive::ArchiveKind;
+use rudu::files_from::{absolute_paths, common_base, read_file_list};
+use rudu::filesystem::is_symlink_loop;
+use rudu::manifest::{read_manifest, ManifestFormat};
+use rudu::units::{parse_block_size, parse_size, BlockSize, SizeFormat};
+use rudu::{
+    rescan_roots_with_cache, resolve_root, scan_roots_with_cache, Cache, CacheEntry, CacheStatus,
+    CancellationToken, DirSizes, HardlinkPolicy, HardlinkStats, MultiRootSizes, ProgressDisplay,
+    PruneStats, Scanner, StopReason, SymlinkStats,
+};
+
+/// Broken or looping symlinks listed individually before summarizing the rest.
+const MAX_SYMLINK_WARNINGS: usize = 10;
+
+/// Width of the `bar` column in characters.
+const BAR_WIDTH: usize = 20;
 
 /// Fast, parallel Rust CLI tool for analyzing directory sizes with colorful output and smart caching.
 #[derive(Parser)]
 #[command(name = "rudu", author, version, about)]
 struct Cli {
-    /// Root directory to analyze
-    #[arg(default_value = "/")]
-    path: PathBuf,
+    /// Root directories to analyze (default: /); several roots are scanned concurrently
+    paths: Vec<PathBuf>,
+
+    /// Aggregate only the files listed in FILE ('-' for stdin), one per line or NUL-separated
+    #[arg(long = "files-from", value_name = "FILE")]
+    files_from: Option<PathBuf>,
+
+    /// Aggregate a listing of paths and sizes from FILE ('-' for stdin) without touching the filesystem
+    #[arg(long = "manifest", value_name = "FILE", conflicts_with_all = ["paths", "files_from"])]
+    manifest: Option<PathBuf>,
+
+    /// Column separator in the manifest: a single character, 'tab' or 'comma'
+    #[arg(long = "separator", value_name = "SEP", default_value = "tab", value_parser = parse_separator, requires = "manifest")]
+    separator: char,
+
+    /// Manifest column holding the path, counting from 1
+    #[arg(long = "path-column", value_name = "N", default_value_t = 1, value_parser = clap::value_parser!(u16).range(1..), requires = "manifest")]
+    path_column: u16,
+
+    /// Manifest column holding the size in bytes, counting from 1
+    #[arg(long = "size-column", value_name = "N", default_value_t = 2, value_parser = clap::value_parser!(u16).range(1..), requires = "manifest")]
+    size_column: u16,
+
+    /// Character separating directory levels in manifest paths
+    #[arg(
+        long = "path-delimiter",
+        value_name = "CHAR",
+        default_value_t = '/',
+        requires = "manifest"
+    )]
+    path_delimiter: char,
+
+    /// Skip the first line of the manifest as a header
+    #[arg(long = "header", requires = "manifest")]
+    header: bool,
+
+    /// Also rank the contents of .tar, .tar.gz and .zip files found while scanning
+    #[arg(long = "archives")]
+    archives: bool,
+
+    /// How to count files with several hard links: once (in the directory of
+    /// their first path), all (every link in full) or proportional (split
+    /// between the linking directories)
+    #[arg(long = "hardlinks", value_name = "POLICY", default_value = "once", value_parser = parse_hardlink_policy)]
+    hardlinks: HardlinkPolicy,
+
+    /// Follow symlinks found while scanning, counting each target once and
+    /// reporting broken links and loops; implies -H
+    #[arg(short = 'L', long = "follow-symlinks")]
+    follow_symlinks: bool,
+
+    /// Follow symlinks given as PATH but keep them under the name given,
+    /// reporting broken links and loops
+    #[arg(short = 'H', long = "dereference-args")]
+    dereference_args: bool,
+
+    /// With several roots, rank directories separately for each root
+    #[arg(long = "per-root")]
+    per_root: bool,
 
     /// How many results to show
     #[arg(short = 'n', long = "number", default_value_t = 10)]
     top: usize,
 
-    /// Suppress informational messages
+    /// Show every directory that passes the filters instead of the first -n
+    #[arg(short = 'a', long = "all", conflicts_with = "top")]
+    all: bool,
+
+    /// Order of the ranking: size (largest first), files (most first), name,
+    /// mtime (newest first) or growth since the last cached scan (largest
+    /// first, needs --cache)
+    #[arg(long = "sort", value_name = "KEY", default_value = "size", value_parser = parse_sort_key)]
+    sort: SortKey,
+
+    /// Reverse the order of the ranking
+    #[arg(short = 'r', long = "reverse")]
+    reverse: bool,
+
+    /// Only rank directories of at least SIZE, e.g. 1G or 500MB
+    #[arg(long = "min-size", value_name = "SIZE", value_parser = parse_size)]
+    min_size: Option<u64>,
+
+    /// Only rank directories of at most SIZE, e.g. 4K or 10MB
+    #[arg(long = "max-size", value_name = "SIZE", value_parser = parse_size)]
+    max_size: Option<u64>,
+
+    /// Only rank directories whose path, as shown, matches REGEX
+    #[arg(long = "match", value_name = "REGEX")]
+    pattern: Option<Regex>,
+
+    /// Columns to show before each path, comma-separated: size, percent (of
+    /// the total), parent (percent of the parent), bar, files, mtime (age of
+    /// the newest file), growth (since the last cached scan, needs --cache)
+    #[arg(long = "columns", value_name = "LIST", default_value = "size", value_delimiter = ',', value_parser = parse_column)]
+    columns: Vec<Column>,
+
+    /// Show sizes in powers of 1024 (KiB, MiB, GiB)
+    #[arg(long = "binary", group = "units")]
+    binary: bool,
+
+    /// Show sizes in powers of 1000 (kB, MB, GB); an explicit alias for the
+    /// default, for scripts that spell out their units
+    #[arg(long = "si", group = "units")]
+    si: bool,
+
+    /// Show sizes as a whole number of SIZE blocks, rounded up (e.g. K, M, G, MB, 512)
+    #[arg(short = 'B', long = "block-size", value_name = "SIZE", group = "units", value_parser = parse_block_size)]
+    block_size: Option<BlockSize>,
+
+    /// Show sizes as exact byte counts
+    #[arg(short = 'b', long = "bytes", group = "units")]
+    bytes: bool,
+
+    /// Suppress informational messages and the progress display
     #[arg(short = 'q', long = "quiet")]
     quiet: bool,
 
@@ -27,6 +153,10 @@ struct Cli {
     #[arg(short = 'c', long = "cache")]
     cache: bool,
 
+    /// Cache directory (default: $RUDU_CACHE_DIR or the user cache directory); implies --cache
+    #[arg(long = "cache-dir", value_name = "DIR")]
+    cache_dir: Option<PathBuf>,
+
     /// Maximum cache age in hours (default: 24)
     #[arg(long = "cache-age", default_value_t = 24)]
     cache_age: u64,
@@ -38,14 +168,233 @@ struct Cli {
     /// Show cache statistics
     #[arg(long = "cache-stats")]
     cache_stats: bool,
+
+    /// Remove expired, orphaned and unreadable cache entries, then evict down to the limits
+    #[arg(long = "prune-cache")]
+    prune_cache: bool,
+
+    /// Maximum total size of the cache, e.g. 2GB or 512MiB; bare numbers are megabytes (default: 500MB)
+    #[arg(long = "cache-max-size", value_name = "SIZE", value_parser = parse_cache_size)]
+    cache_max_size: Option<u64>,
+
+    /// Maximum number of cache entries (default: 1000)
+    #[arg(long = "cache-max-entries", value_name = "N")]
+    cache_max_entries: Option<usize>,
+
+    /// List cached scans with their age, size, file count and scan options
+    #[arg(long = "cache-list")]
+    cache_list: bool,
+
+    /// Print the cached report for PATH without rescanning, whatever its age
+    #[arg(long = "cache-show", value_name = "PATH")]
+    cache_show: Option<PathBuf>,
+
+    /// Remove the cached scan of PATH
+    #[arg(long = "cache-invalidate", value_name = "PATH")]
+    cache_invalidate: Option<PathBuf>,
+
+    /// Remove the cached scans of PATH and every directory below it
+    #[arg(long = "cache-invalidate-under", value_name = "PATH")]
+    cache_invalidate_under: Option<PathBuf>,
+
+    /// Stop scanning after this long and show partial results (e.g. 30s, 5m, 1h)
+    #[arg(long = "timeout", value_parser = parse_duration)]
+    timeout: Option<Duration>,
+}
+
+/// Parse a duration such as `500ms`, `30s`, `5m` or `1h`; bare numbers are seconds.
+fn parse_duration(value: &str) -> Result<Duration, String> {
+    let value = value.trim();
+    let split = value
+        .find(|c: char| !c.is_ascii_digit() && c != '.')
+        .unwrap_or(value.len());
+    let (number, unit) = value.split_at(split);
+    let number: f64 = number
+        .parse()
+        .map_err(|_| format!("invalid duration '{value}'"))?;
+
+    let seconds = match unit.trim() {
+        "ms" => number / 1000.0,
+        "" | "s" => number,
+        "m" => number * 60.0,
+        "h" => number * 3600.0,
+        other => {
+            return Err(format!(
+                "unknown duration unit '{other}' (use ms, s, m or h)"
+            ))
+        }
+    };
+    Duration::try_from_secs_f64(seconds).map_err(|e| e.to_string())
+}
+
+/// Parse a `--cache-max-size` limit in bytes. Bare numbers are megabytes, as
+/// before units were accepted.
+fn parse_cache_size(value: &str) -> Result<u64, String> {
+    match value.trim().parse::<u64>() {
+        Ok(megabytes) => Ok(megabytes.saturating_mul(1_000_000)),
+        Err(_) => parse_size(value),
+    }
+}
+
+/// Parse a manifest column separator: a single character, `tab` or `comma`.
+fn parse_separator(value: &str) -> Result<char, String> {
+    match value {
+        "tab" | "\\t" => Ok('\t'),
+        "comma" => Ok(','),
+        _ => {
+            let mut chars = value.chars();
+            match (chars.next(), chars.next()) {
+                (Some(c), None) => Ok(c),
+                _ => Err(format!(
+                    "invalid separator '{value}' (use a single character, 'tab' or 'comma')"
+                )),
+            }
+        }
+    }
+}
+
+/// Parse a hardlink accounting policy: `once`, `all` or `proportional`.
+fn parse_hardlink_policy(value: &str) -> Result<HardlinkPolicy, String> {
+    match value {
+        "once" => Ok(HardlinkPolicy::Once),
+        "all" => Ok(HardlinkPolicy::All),
+        "proportional" => Ok(HardlinkPolicy::Proportional),
+        _ => Err(format!(
+            "invalid hardlink policy '{value}' (use 'once', 'all' or 'proportional')"
+        )),
+    }
+}
+
+/// A column of the ranking, shown before the path.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Column {
+    /// Size of the directory
+    Size,
+    /// Share of the total size
+    Percent,
+    /// Share of the parent directory's size
+    Parent,
+    /// Bar scaled to the largest directory shown
+    Bar,
+    /// Number of files below the directory
+    Files,
+    /// Time since the newest file below the directory was modified
+    Mtime,
+    /// Change in size since the last cached scan
+    Growth,
+}
+
+/// Parse a ranking column name: `size`, `percent`, `parent`, `bar`, `files`,
+/// `mtime` or `growth`.
+fn parse_column(value: &str) -> Result<Column, String> {
+    match value.trim() {
+        "size" => Ok(Column::Size),
+        "percent" => Ok(Column::Percent),
+        "parent" => Ok(Column::Parent),
+        "bar" => Ok(Column::Bar),
+        "files" => Ok(Column::Files),
+        "mtime" => Ok(Column::Mtime),
+        "growth" => Ok(Column::Growth),
+        other => Err(format!(
+            "invalid column '{other}' (use size, percent, parent, bar, files, mtime or growth)"
+        )),
+    }
+}
+
+/// Order of the ranking.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum SortKey {
+    /// Largest first
+    Size,
+    /// Most files first
+    Files,
+    /// By path
+    Name,
+    /// Most recently modified first
+    Mtime,
+    /// Largest growth first
+    Growth,
+}
+
+impl SortKey {
+    /// The column showing this key, if it is not the path or size.
+    fn column(self) -> Option<Column> {
+        match self {
+            SortKey::Size | SortKey::Name => None,
+            SortKey::Files => Some(Column::Files),
+            SortKey::Mtime => Some(Column::Mtime),
+            SortKey::Growth => Some(Column::Growth),
+        }
+    }
+}
+
+/// Parse a sort key: `size`, `files`, `name`, `mtime` or `growth`.
+fn parse_sort_key(value: &str) -> Result<SortKey, String> {
+    match value.trim() {
+        "size" => Ok(SortKey::Size),
+        "files" => Ok(SortKey::Files),
+        "name" => Ok(SortKey::Name),
+        "mtime" => Ok(SortKey::Mtime),
+        "growth" => Ok(SortKey::Growth),
+        other => Err(format!(
+            "invalid sort key '{other}' (use size, files, name, mtime or growth)"
+        )),
+    }
+}
+
+impl Cli {
+    /// The unit sizes are shown in.
+    fn units(&self) -> SizeFormat {
+        if self.binary {
+            SizeFormat::Binary
+        } else if let Some(block) = self.block_size {
+            SizeFormat::Blocks(block)
+        } else if self.bytes {
+            SizeFormat::Bytes
+        } else {
+            // `--si`, or no unit option at all
+            SizeFormat::Decimal
+        }
+    }
+
+    /// The columns to show: those asked for, plus the one the ranking is
+    /// sorted by.
+    fn shown_columns(&self) -> Vec<Column> {
+        let mut columns = self.columns.clone();
+        if let Some(column) = self.sort.column().filter(|c| !columns.contains(c)) {
+            columns.push(column);
+        }
+        columns
+    }
+
+    /// Whether growth since the last cached scan is needed.
+    fn needs_growth(&self) -> bool {
+        self.sort == SortKey::Growth || self.columns.contains(&Column::Growth)
+    }
+
+    /// Which directories to rank and how, showing `columns`.
+    fn view<'a>(&'a self, columns: &'a [Column]) -> View<'a> {
+        View {
+            columns,
+            sort: self.sort,
+            reverse: self.reverse,
+            min_size: self.min_size,
+            max_size: self.max_size,
+            pattern: self.pattern.as_ref(),
+            limit: (!self.all).then_some(self.top),
+        }
+    }
 }
 
 fn main() {
     let cli = Cli::parse();
+    let units = cli.units();
+    let columns = cli.shown_columns();
+    let view = cli.view(&columns);
 
     // Handle cache operations first
     if cli.clear_cache {
-        match Cache::new() {
+        match open_cache(&cli) {
             Ok(cache) => match cache.clear() {
                 Ok(()) => {
                     println!(
@@ -68,7 +417,7 @@ fn main() {
     }
 
     if cli.cache_stats {
-        match Cache::new() {
+        match open_cache(&cli) {
             Ok(cache) => match cache.stats() {
                 Ok((count, size)) => {
                     println!(
@@ -84,7 +433,7 @@ fn main() {
                     println!(
                         "💾 {}: {}",
                         "Cache size".bright_cyan(),
-                        format_size(size, DECIMAL).bright_yellow().bold()
+                        units.format(size).bright_yellow().bold()
                     );
                     return;
                 }
@@ -104,78 +453,874 @@ fn main() {
         }
     }
 
-    let base = match cli.path.canonicalize() {
-        Ok(p) => p,
-        Err(err) => {
+    if cli.prune_cache {
+        match open_cache(&cli) {
+            Ok(cache) => match cache.prune(cli.cache_age * 3600) {
+                Ok(stats) => {
+                    print_prune_stats(&cache, &stats, units);
+                    return;
+                }
+                Err(e) => {
+                    eprintln!("❌ {}: {}", "Failed to prune cache".bright_red().bold(), e);
+                    process::exit(1);
+                }
+            },
+            Err(e) => {
+                eprintln!("❌ {}: {}", "Failed to access cache".bright_red().bold(), e);
+                process::exit(1);
+            }
+        }
+    }
+
+    if cli.cache_list
+        || cli.cache_show.is_some()
+        || cli.cache_invalidate.is_some()
+        || cli.cache_invalidate_under.is_some()
+    {
+        let cache = open_cache(&cli).unwrap_or_else(|e| fail("Failed to access cache", e));
+        if cli.cache_list {
+            print_cache_list(&cache, units);
+        }
+        if let Some(path) = &cli.cache_invalidate {
+            let path = cache_lookup_path(path);
+            match cache.invalidate(&path) {
+                Ok(removed) => println!(
+                    "🗑️  {} {}",
+                    if removed {
+                        "Invalidated cached scan of"
+                    } else {
+                        "No cached scan of"
+                    }
+                    .bright_green()
+                    .bold(),
+                    path.display().to_string().bright_white()
+                ),
+                Err(e) => fail("Failed to invalidate cache entry", e),
+            }
+        }
+        if let Some(prefix) = &cli.cache_invalidate_under {
+            let prefix = cache_lookup_path(prefix);
+            match cache.invalidate_under(&prefix) {
+                Ok(count) => println!(
+                    "🗑️  {} {}",
+                    format!("Invalidated {count} cached scans under")
+                        .bright_green()
+                        .bold(),
+                    prefix.display().to_string().bright_white()
+                ),
+                Err(e) => fail("Failed to invalidate cache entries", e),
+            }
+        }
+        if let Some(path) = &cli.cache_show {
+            show_cached_scan(&cache, &cache_lookup_path(path), &view, units);
+        }
+        return;
+    }
+
+    let files = cli.files_from.as_deref().map(read_files_from);
+    let bases: Vec<PathBuf> = match &files {
+        Some(files) => vec![files_from_base(&cli.paths, files)],
+        // Manifest paths are virtual, so they are placed under "/" without resolving it
+        None if cli.manifest.is_some() => vec![PathBuf::from("/")],
+        None if cli.paths.is_empty() => vec![resolve_path(Path::new("/"))],
+        None if cli.dereference_args || cli.follow_symlinks => {
+            cli.paths.iter().map(|path| resolve_arg(path)).collect()
+        }
+        None => cli.paths.iter().map(|path| resolve_path(path)).collect(),
+    };
+
+    for base in &bases {
+        match &files {
+            None if cli.manifest.is_some() => eprintln!(
+                "📋 {} {}",
+                "Reading manifest:".bright_cyan().bold(),
+                cli.manifest
+                    .as_deref()
+                    .unwrap_or(base)
+                    .display()
+                    .to_string()
+                    .bright_white()
+            ),
+            Some(files) => eprintln!(
+                "📄 {} {}",
+                format!("Reading {} listed files under", files.len())
+                    .bright_cyan()
+                    .bold(),
+                base.display().to_string().bright_white()
+            ),
+            None => eprintln!(
+                "🔍 {} {}",
+                "Scanning directory:".bright_cyan().bold(),
+                base.display().to_string().bright_white()
+            ),
+        }
+    }
+
+    // First Ctrl-C stops the scan and prints partial results; a second one exits immediately
+    let token = CancellationToken::new();
+    {
+        let token = token.clone();
+        let _ = ctrlc::set_handler(move || {
+            if token.is_cancelled() {
+                process::exit(130);
+            }
+            token.cancel();
+        });
+    }
+
+    // With several roots, show current directories as full paths
+    let display_base = match bases.as_slice() {
+        [base] => base.as_path(),
+        _ => Path::new(""),
+    };
+    let display = (!cli.quiet).then(|| ProgressDisplay::new(display_base).units(units));
+    // Shown once the progress display is gone
+    let symlink_problems = Mutex::new(Vec::new());
+    let scanners: Vec<Scanner> = bases
+        .iter()
+        .enumerate()
+        .map(|(index, base)| {
+            let mut scanner = Scanner::new(base)
+                .cancel_token(token.clone())
+                .archives(cli.archives)
+                .hardlinks(cli.hardlinks)
+                .follow_symlinks(cli.follow_symlinks)
+                .on_error(|error| {
+                    if matches!(
+                        error,
+                        rudu::Error::BrokenSymlink { .. } | rudu::Error::SymlinkLoop { .. }
+                    ) {
+                        let mut problems =
+                            symlink_problems.lock().unwrap_or_else(|e| e.into_inner());
+                        problems.push(error.to_string());
+                    }
+                });
+            if let Some(timeout) = cli.timeout {
+                scanner = scanner.timeout(timeout);
+            }
+            if let Some(display) = display.as_ref().filter(|display| display.is_enabled()) {
+                scanner = scanner.on_progress(move |progress| display.update_root(index, progress));
+            }
+            scanner
+        })
+        .collect();
+
+    // An unusable cache only costs speed, so scan without it
+    let use_cache = cli.cache || cli.cache_dir.is_some();
+    let cache = match use_cache.then(|| open_cache(&cli)) {
+        Some(Ok(cache)) => Some(cache),
+        Some(Err(e)) => {
+            if !cli.quiet {
+                eprintln!("⚠️  Warning: Cache not used: {e}");
+            }
+            None
+        }
+        None => None,
+    };
+
+    // Growth is measured against the scans cached before this one, which
+    // are then replaced by a fresh scan
+    let previous: Option<Vec<Option<CacheEntry>>> = cli.needs_growth().then(|| {
+        if files.is_some() || cli.manifest.is_some() {
+            fail(
+                "Growth is not available",
+                "listed files and manifests are never cached",
+            );
+        }
+        let cache = cache.as_ref().unwrap_or_else(|| {
+            fail(
+                "Growth is not available",
+                "it is measured against the last cached scan, so --cache is needed",
+            )
+        });
+        scanners
+            .iter()
+            .map(|scanner| {
+                let base = scanner.base();
+                let entry = cache
+                    .get_with_options(base, &scanner.options())
+                    .ok()
+                    .flatten();
+                if entry.is_none() && !cli.quiet {
+                    eprintln!(
+                        "⚠️  No earlier cached scan of {}, so growth is unknown until the next run",
+                        base.display()
+                    );
+                }
+                entry
+            })
+            .collect()
+    });
+
+    // Listed files are never cached, since the result depends on the list
+    let result = match (&files, &cli.manifest) {
+        (Some(files), _) => {
+            let sizes = DirSizes::from(scanners[0].scan_files(files));
+            MultiRootSizes::single(bases[0].clone(), sizes)
+        }
+        (None, Some(manifest)) => {
+            let format = ManifestFormat {
+                separator: cli.separator,
+                path_column: usize::from(cli.path_column) - 1,
+                size_column: usize::from(cli.size_column) - 1,
+                path_delimiter: cli.path_delimiter,
+                has_header: cli.header,
+            };
+            let sizes = DirSizes::from(load_manifest(manifest, &format, &bases[0]));
+            MultiRootSizes::single(bases[0].clone(), sizes)
+        }
+        (None, None) => {
+            let result = match (&cache, &previous) {
+                (Some(cache), Some(_)) => rescan_roots_with_cache(&scanners, cache),
+                _ => scan_roots_with_cache(&scanners, cache.as_ref(), cli.cache_age),
+            };
+            result.unwrap_or_else(|err| {
+                if let Some(display) = &display {
+                    display.finish();
+                }
+                eprintln!("❌ {}: {}", "Error".bright_red().bold(), err);
+                process::exit(1);
+            })
+        }
+    };
+    let growth: Vec<HashMap<PathBuf, i64>> = match &previous {
+        Some(previous) => result
+            .roots
+            .iter()
+            .zip(previous)
+            .map(|((_, sizes), entry)| {
+                entry
+                    .as_ref()
+                    .map(|entry| growth_since(&sizes.sizes, entry))
+                    .unwrap_or_default()
+            })
+            .collect(),
+        None => vec![HashMap::new(); result.roots.len()],
+    };
+    if let Some(display) = &display {
+        display.finish();
+    }
+    if !cli.quiet {
+        for (base, sizes) in &result.roots {
+            print_cache_status(base, &sizes.cache);
+        }
+        let mut problems =
+            std::mem::take(&mut *symlink_problems.lock().unwrap_or_else(|e| e.into_inner()));
+        problems.sort();
+        for problem in problems.iter().take(MAX_SYMLINK_WARNINGS) {
+            eprintln!("⚠️  {}", problem.bright_yellow());
+        }
+        if problems.len() > MAX_SYMLINK_WARNINGS {
             eprintln!(
-                "❌ {}: failed to resolve path '{}': {}",
+                "⚠️  {}",
+                format!(
+                    "... and {} more symlink problems",
+                    problems.len() - MAX_SYMLINK_WARNINGS
+                )
+                .bright_yellow()
+            );
+        }
+    }
+
+    let errors: u64 = result.roots.iter().map(|(_, sizes)| sizes.errors).sum();
+    if files.is_some() && errors > 0 && !cli.quiet {
+        eprintln!(
+            "⚠️  {} listed paths could not be read or are outside {}",
+            errors.to_string().bright_yellow().bold(),
+            bases[0].display().to_string().bright_white()
+        );
+    }
+    if cli.manifest.is_some() && errors > 0 && !cli.quiet {
+        eprintln!(
+            "⚠️  {} manifest lines were skipped (missing path or invalid size)",
+            errors.to_string().bright_yellow().bold()
+        );
+    }
+
+    let stopped = result.stopped();
+    if let Some(reason) = stopped {
+        eprintln!(
+            "⚠️  {} {}",
+            format!("Scan {}:", incomplete_message(reason))
+                .bright_yellow()
+                .bold(),
+            "sizes below are partial".bright_yellow()
+        );
+    }
+
+    if let [(base, sizes)] = result.roots.as_slice() {
+        let ranking = Ranking {
+            entries: ranked_entries(&sizes.sizes, &[base]),
+            sizes: &sizes.sizes,
+            file_counts: &sizes.file_counts,
+            modified: &sizes.modified,
+            growth: &growth[0],
+            compressed: &sizes.compressed,
+            base: Some(base),
+            total: result.total_bytes,
+            units,
+        };
+        print_ranking(&ranking, &view);
+        print_summary(
+            &base.display().to_string(),
+            result.total_bytes,
+            sizes.total_files,
+            sizes.duration,
+            units,
+        );
+        print_hardlink_stats(&sizes.hardlink_stats, units);
+        print_symlink_stats(&sizes.symlinks);
+        if let Some(compressed) = sizes.compressed.get(base) {
+            println!(
+                "🗜️  {}: {}",
+                "Compressed size".bright_cyan(),
+                units.format(*compressed).bright_yellow().bold()
+            );
+        }
+    } else {
+        if cli.per_root {
+            for ((base, sizes), growth) in result.roots.iter().zip(&growth) {
+                let total = sizes.sizes.get(base).copied().unwrap_or(0);
+                println!(
+                    "📂 {} {}",
+                    base.display().to_string().bright_white().bold(),
+                    format!("({})", units.format(total)).bright_blue()
+                );
+                let ranking = Ranking {
+                    entries: ranked_entries(&sizes.sizes, &[base]),
+                    sizes: &sizes.sizes,
+                    file_counts: &sizes.file_counts,
+                    modified: &sizes.modified,
+                    growth,
+                    compressed: &sizes.compressed,
+                    base: Some(base),
+                    total,
+                    units,
+                };
+                print_ranking(&ranking, &view);
+            }
+        } else {
+            // Overlapping roots report the same directories, so merge by path
+            let mut merged: HashMap<PathBuf, u64> = HashMap::new();
+            let mut file_counts: HashMap<PathBuf, u64> = HashMap::new();
+            let mut modified: HashMap<PathBuf, u64> = HashMap::new();
+            let mut merged_growth: HashMap<PathBuf, i64> = HashMap::new();
+            let mut compressed: HashMap<PathBuf, u64> = HashMap::new();
+            for ((_, sizes), growth) in result.roots.iter().zip(&growth) {
+                for (path, bytes) in &sizes.sizes {
+                    merged.entry(path.clone()).or_insert(*bytes);
+                }
+                for (path, files) in &sizes.file_counts {
+                    file_counts.entry(path.clone()).or_insert(*files);
+                }
+                for (path, time) in &sizes.modified {
+                    modified.entry(path.clone()).or_insert(*time);
+                }
+                for (path, change) in growth {
+                    merged_growth.entry(path.clone()).or_insert(*change);
+                }
+                compressed.extend(sizes.compressed.iter().map(|(p, b)| (p.clone(), *b)));
+            }
+            // Roots nested inside another root are ranked like any other directory
+            let roots: Vec<&PathBuf> = result.roots.iter().map(|(base, _)| base).collect();
+            let outer_roots: Vec<&PathBuf> = roots
+                .iter()
+                .copied()
+                .filter(|root| {
+                    !roots
+                        .iter()
+                        .any(|other| other != root && root.starts_with(other))
+                })
+                .collect();
+            let ranking = Ranking {
+                entries: ranked_entries(&merged, &outer_roots),
+                sizes: &merged,
+                file_counts: &file_counts,
+                modified: &modified,
+                growth: &merged_growth,
+                compressed: &compressed,
+                base: None,
+                total: result.total_bytes,
+                units,
+            };
+            print_ranking(&ranking, &view);
+        }
+
+        print_summary(
+            &format!("{} roots", result.roots.len()),
+            result.total_bytes,
+            result.total_files,
+            result.duration,
+            units,
+        );
+        let mut hardlink_stats = HardlinkStats::default();
+        let mut symlink_stats = SymlinkStats::default();
+        for (_, sizes) in &result.roots {
+            hardlink_stats.merge(&sizes.hardlink_stats);
+            symlink_stats.merge(&sizes.symlinks);
+        }
+        print_hardlink_stats(&hardlink_stats, units);
+        print_symlink_stats(&symlink_stats);
+        if result.shared_bytes > 0 {
+            println!(
+                "🔗 {}: {}",
+                "Hardlinked across roots (counted once)".bright_cyan(),
+                units.format(result.shared_bytes).bright_yellow().bold()
+            );
+        }
+    }
+
+    if let Some(reason) = stopped {
+        println!(
+            "⚠️  {}: {}",
+            "Status".bright_cyan(),
+            format!("incomplete ({})", incomplete_message(reason))
+                .bright_red()
+                .bold()
+        );
+        if reason == StopReason::Cancelled {
+            process::exit(130);
+        }
+    }
+}
+
+/// Open the cache in `--cache-dir` if given, otherwise in the default location,
+/// with the limits given on the command line.
+fn open_cache(cli: &Cli) -> rudu::Result<Cache> {
+    let mut cache = match &cli.cache_dir {
+        Some(dir) => Cache::with_dir(dir)?,
+        None => Cache::new()?,
+    };
+    if let Some(bytes) = cli.cache_max_size {
+        cache = cache.max_size(bytes);
+    }
+    if let Some(entries) = cli.cache_max_entries {
+        cache = cache.max_entries(entries);
+    }
+    Ok(cache)
+}
+
+/// Print an error message for a failed cache command and exit.
+fn fail(context: &str, err: impl std::fmt::Display) -> ! {
+    eprintln!("❌ {}: {}", context.bright_red().bold(), err);
+    process::exit(1);
+}
+
+/// Resolve a path given to a cache command. Paths that no longer exist are
+/// made absolute without resolving them, so their entries can still be found.
+fn cache_lookup_path(path: &Path) -> PathBuf {
+    resolve_root(path).unwrap_or_else(|_| match std::env::current_dir() {
+        Ok(cwd) => cwd.join(path),
+        Err(_) => path.to_path_buf(),
+    })
+}
+
+/// Format the age of a cache entry, e.g. `42s`, `15m`, `3h` or `2d`.
+fn format_age(age: Duration) -> String {
+    let secs = age.as_secs();
+    match secs {
+        0..=59 => format!("{secs}s"),
+        60..=3599 => format!("{}m", secs / 60),
+        3600..=86399 => format!("{}h", secs / 3600),
+        _ => format!("{}d", secs / 86400),
+    }
+}
+
+/// List the entries of `cache` for `--cache-list`.
+fn print_cache_list(cache: &Cache, units: SizeFormat) {
+    let entries = cache
+        .list()
+        .unwrap_or_else(|e| fail("Failed to list cache", e));
+    println!(
+        "🗂️  {} {}",
+        format!("{} cached scans", entries.len())
+            .bright_green()
+            .bold(),
+        format!("({})", cache.cache_directory().display()).bright_blue()
+    );
+    for entry in entries {
+        println!(
+            "{:>10}  {:>10}  {:>5}  {:<10}  {}",
+            units.format(entry.size).bright_yellow(),
+            format!("{} files", entry.total_files).bright_cyan(),
+            format_age(entry.age()).bright_magenta(),
+            entry.options.to_string().bright_blue(),
+            entry.base_path.display().to_string().bright_white()
+        );
+    }
+}
+
+/// Print the cached report for `base` for `--cache-show`, exiting if there is none.
+fn show_cached_scan(cache: &Cache, base: &Path, view: &View, units: SizeFormat) {
+    let entry = match cache.get(base) {
+        Ok(Some(entry)) => entry,
+        Ok(None) => fail("No cached scan", base.display()),
+        Err(e) => fail("Failed to read cache", e),
+    };
+    println!(
+        "📦 {} {} {}",
+        "Cached scan of".bright_green().bold(),
+        base.display().to_string().bright_white(),
+        format!(
+            "(stored {} ago, {})",
+            format_age(entry.age()),
+            entry.options
+        )
+        .bright_blue()
+    );
+
+    let sizes = DirSizes::from(entry);
+    let total = sizes.sizes.get(base).copied().unwrap_or(0);
+    let ranking = Ranking {
+        entries: ranked_entries(&sizes.sizes, &[&base.to_path_buf()]),
+        sizes: &sizes.sizes,
+        file_counts: &sizes.file_counts,
+        modified: &sizes.modified,
+        growth: &HashMap::new(),
+        compressed: &sizes.compressed,
+        base: Some(base),
+        total,
+        units,
+    };
+    print_ranking(&ranking, view);
+    print_summary(
+        &base.display().to_string(),
+        total,
+        sizes.total_files,
+        sizes.duration,
+        units,
+    );
+}
+
+/// Report what `--prune-cache` removed.
+fn print_prune_stats(cache: &Cache, stats: &PruneStats, units: SizeFormat) {
+    println!(
+        "🧹 {} {}",
+        "Cache pruned".bright_green().bold(),
+        format!("({})", cache.cache_directory().display()).bright_blue()
+    );
+    for (label, count) in [
+        ("Expired entries", stats.expired),
+        ("Orphaned entries", stats.orphaned),
+        ("Corrupt entries", stats.corrupt),
+        ("Evicted entries", stats.evicted),
+    ] {
+        println!(
+            "📁 {}: {}",
+            label.bright_cyan(),
+            count.to_string().bright_yellow().bold()
+        );
+    }
+    println!(
+        "💾 {}: {}",
+        "Space freed".bright_cyan(),
+        units.format(stats.freed_bytes).bright_yellow().bold()
+    );
+}
+
+/// Canonicalize a root path, exiting with an error message if it cannot be resolved.
+fn resolve_path(path: &Path) -> PathBuf {
+    resolve_root(path).unwrap_or_else(|err| {
+        eprintln!(
+            "❌ {}: {}",
+            "Error".bright_red().bold(),
+            err.to_string().bright_red()
+        );
+        process::exit(1);
+    })
+}
+
+/// Resolve a root path for -H: a symlink is followed but kept under the name
+/// given, with only the directories above it canonicalized.
+///
+/// A symlink to an archive is replaced by the archive's own path instead,
+/// since the target's name is what tells which kind of archive it is.
+fn resolve_arg(path: &Path) -> PathBuf {
+    let is_symlink = fs::symlink_metadata(path).is_ok_and(|m| m.file_type().is_symlink());
+    let (true, Some(parent), Some(name)) = (is_symlink, path.parent(), path.file_name()) else {
+        return resolve_path(path);
+    };
+    if let Err(source) = fs::metadata(path) {
+        let path = path.to_path_buf();
+        let err = if is_symlink_loop(&source) {
+            rudu::Error::SymlinkLoop { path }
+        } else {
+            rudu::Error::BrokenSymlink { path, source }
+        };
+        eprintln!(
+            "❌ {}: {}",
+            "Error".bright_red().bold(),
+            err.to_string().bright_red()
+        );
+        process::exit(1);
+    }
+    let target = resolve_path(path);
+    if ArchiveKind::from_path(&target).is_some() && target.is_file() {
+        return target;
+    }
+    let parent = if parent.as_os_str().is_empty() {
+        Path::new(".")
+    } else {
+        parent
+    };
+    resolve_path(parent).join(name)
+}
+
+/// Tell the user how the cache was used for `base`.
+fn print_cache_status(base: &Path, status: &CacheStatus) {
+    let base = base.display().to_string();
+    match status {
+        CacheStatus::Hit => eprintln!(
+            "🚀 {} {}",
+            "Using cached results for".bright_green().bold(),
+            base.bright_white()
+        ),
+        CacheStatus::ParentHit => eprintln!(
+            "🚀 {} {}",
+            "Using parent cache for".bright_green().bold(),
+            base.bright_white()
+        ),
+        CacheStatus::Stored => eprintln!(
+            "💾 {} {}",
+            "Cached results for".bright_blue(),
+            base.bright_white()
+        ),
+        CacheStatus::Composed(reused) => eprintln!(
+            "💾 {} {} {}",
+            "Cached results for".bright_blue(),
+            base.bright_white(),
+            format!("(reused {reused} cached subdirectory scans)").bright_green()
+        ),
+        CacheStatus::Failed(err) => eprintln!("⚠️  Warning: Cache not used for {base}: {err}"),
+        CacheStatus::Disabled | CacheStatus::Incomplete => {}
+    }
+}
+
+/// Read the file list for `--files-from` as absolute paths, exiting on error.
+fn read_files_from(source: &Path) -> Vec<PathBuf> {
+    let list = if source == Path::new("-") {
+        read_file_list(io::stdin().lock())
+    } else {
+        File::open(source).and_then(read_file_list)
+    };
+
+    match (list, std::env::current_dir()) {
+        (Ok(files), Ok(cwd)) => absolute_paths(&files, &cwd),
+        (Err(err), _) | (_, Err(err)) => {
+            eprintln!(
+                "❌ {}: failed to read file list '{}': {}",
                 "Error".bright_red().bold(),
-                cli.path.display().to_string().bright_white(),
+                source.display().to_string().bright_white(),
                 err.to_string().bright_red()
             );
             process::exit(1);
         }
+    }
+}
+
+/// Read a `--manifest` listing into a tree under `root`, exiting on error.
+fn load_manifest(source: &Path, format: &ManifestFormat, root: &Path) -> rudu::ScanResult {
+    let result = if source == Path::new("-") {
+        read_manifest(io::stdin().lock(), format, root)
+    } else {
+        File::open(source).and_then(|file| read_manifest(BufReader::new(file), format, root))
     };
 
-    eprintln!(
-        "🔍 {} {}",
-        "Scanning directory:".bright_cyan().bold(),
-        base.display().to_string().bright_white()
-    );
+    result.unwrap_or_else(|err| {
+        eprintln!(
+            "❌ {}: failed to read manifest '{}': {}",
+            "Error".bright_red().bold(),
+            source.display().to_string().bright_white(),
+            err.to_string().bright_red()
+        );
+        process::exit(1);
+    })
+}
 
-    let (sizes, total_files, duration) =
-        compute_dir_sizes_with_cache(&base, cli.quiet, cli.cache, cli.cache_age);
+/// Pick the base directory for `--files-from`: the explicit path if one was
+/// given, otherwise the deepest directory containing every listed file.
+fn files_from_base(paths: &[PathBuf], files: &[PathBuf]) -> PathBuf {
+    match paths {
+        [] => common_base(files).unwrap_or_else(|| PathBuf::from("/")),
+        [path] => resolve_path(path),
+        _ => {
+            eprintln!(
+                "❌ {}: --files-from accepts at most one base path",
+                "Error".bright_red().bold()
+            );
+            process::exit(1);
+        }
+    }
+}
 
-    let mut entries: Vec<(PathBuf, u64)> = sizes.into_iter().collect();
-    entries.par_sort_unstable_by(|a, b| b.1.cmp(&a.1));
+/// Collect `sizes` into a list, leaving out the scan roots.
+fn ranked_entries(sizes: &HashMap<PathBuf, u64>, roots: &[&PathBuf]) -> Vec<(PathBuf, u64)> {
+    sizes
+        .iter()
+        .filter(|(path, _)| !roots.contains(path))
+        .map(|(path, bytes)| (path.clone(), *bytes))
+        .collect()
+}
 
-    // Get the base directory size for summary
-    let base_size = entries
+/// How much each directory in `sizes` grew since the cached scan `previous`;
+/// directories that did not exist then grew from nothing.
+fn growth_since(sizes: &HashMap<PathBuf, u64>, previous: &CacheEntry) -> HashMap<PathBuf, i64> {
+    sizes
         .iter()
-        .find(|(path, _)| path == &base)
-        .map(|(_, size)| *size)
-        .unwrap_or(0);
+        .map(|(path, &bytes)| {
+            let before = previous.sizes.get(path).copied().unwrap_or(0);
+            (path.clone(), bytes as i64 - before as i64)
+        })
+        .collect()
+}
 
-    let mut display_count = 0;
-    for (path, bytes) in entries.into_iter() {
-        // Skip the base directory in the list
-        if path == base {
-            continue;
-        }
+/// Directories to rank, and what their columns are computed from.
+struct Ranking<'a> {
+    /// Directories in no particular order
+    entries: Vec<(PathBuf, u64)>,
+    /// Size of every directory, including the ones left out of `entries`
+    sizes: &'a HashMap<PathBuf, u64>,
+    /// Files below every directory, where known
+    file_counts: &'a HashMap<PathBuf, u64>,
+    /// Newest modification time below every directory, where known
+    modified: &'a HashMap<PathBuf, u64>,
+    /// Growth of every directory since the last cached scan, where known
+    growth: &'a HashMap<PathBuf, i64>,
+    /// Compressed size of archive contents, where known
+    compressed: &'a HashMap<PathBuf, u64>,
+    /// Paths are shown relative to this when given
+    base: Option<&'a Path>,
+    /// Size the `percent` column is relative to
+    total: u64,
+    /// Unit sizes are shown in
+    units: SizeFormat,
+}
 
-        // Stop if we've shown enough entries
-        if display_count >= cli.top {
-            break;
+impl Ranking<'_> {
+    /// How `path` is shown: relative to the base, or in full without one.
+    fn display_path(&self, path: &Path) -> String {
+        match self.base.map(|base| path.strip_prefix(base)) {
+            Some(Ok(relative)) => relative.display().to_string(),
+            _ => path.display().to_string(),
         }
+    }
 
-        let human = format_size(bytes, DECIMAL);
-
-        // Remove the base directory prefix from the displayed path
-        let display_path = if let Ok(relative) = path.strip_prefix(&base) {
-            // Show relative path from base
-            let rel_str = relative.display().to_string();
-            if rel_str.is_empty() {
-                continue; // Skip empty relative paths
-            } else {
-                rel_str
-            }
-        } else {
-            // Fallback to full path if strip_prefix fails
-            path.display().to_string()
+    /// Order two entries by `key` in its natural direction, or the opposite
+    /// one if `reverse`, then by path. Entries whose key is unknown come last
+    /// unless reversed.
+    fn compare(
+        &self,
+        key: SortKey,
+        reverse: bool,
+        a: (&PathBuf, u64),
+        b: (&PathBuf, u64),
+    ) -> Ordering {
+        let order = match key {
+            SortKey::Size => b.1.cmp(&a.1),
+            SortKey::Files => self.file_counts.get(b.0).cmp(&self.file_counts.get(a.0)),
+            SortKey::Name => a.0.cmp(b.0),
+            SortKey::Mtime => self.modified.get(b.0).cmp(&self.modified.get(a.0)),
+            SortKey::Growth => self.growth.get(b.0).cmp(&self.growth.get(a.0)),
         };
+        let order = if reverse { order.reverse() } else { order };
+        order.then_with(|| a.0.cmp(b.0))
+    }
+}
+
+/// Which directories of a ranking to show, and how.
+struct View<'a> {
+    /// Columns shown before each path
+    columns: &'a [Column],
+    /// Order of the directories
+    sort: SortKey,
+    /// Whether to reverse that order
+    reverse: bool,
+    /// Smallest size shown
+    min_size: Option<u64>,
+    /// Largest size shown
+    max_size: Option<u64>,
+    /// Only paths matching this, as shown, are ranked
+    pattern: Option<&'a Regex>,
+    /// Number of directories shown, or all of them if `None`
+    limit: Option<usize>,
+}
+
+impl View<'_> {
+    /// Whether a directory of `bytes` is within the size limits.
+    fn fits(&self, bytes: u64) -> bool {
+        self.min_size.map_or(true, |min| bytes >= min)
+            && self.max_size.map_or(true, |max| bytes <= max)
+    }
+}
+
+/// Print the entries of `ranking` that pass the filters of `view`, in its
+/// order and with its columns before each path, and the compressed size of
+/// archive contents where known.
+fn print_ranking(ranking: &Ranking, view: &View) {
+    let mut selected: Vec<(&PathBuf, u64)> = ranking
+        .entries
+        .iter()
+        .filter(|(path, bytes)| Some(path.as_path()) != ranking.base && view.fits(*bytes))
+        .filter(|(path, _)| {
+            view.pattern.map_or(true, |pattern| {
+                pattern.is_match(&ranking.display_path(path))
+            })
+        })
+        .map(|(path, bytes)| (path, *bytes))
+        .collect();
+    selected.par_sort_unstable_by(|a, b| ranking.compare(view.sort, view.reverse, *a, *b));
+    if let Some(limit) = view.limit {
+        selected.truncate(limit);
+    }
+    // Remove the base directory prefix from the displayed paths
+    let shown: Vec<(&PathBuf, u64, String)> = selected
+        .into_iter()
+        .map(|(path, bytes)| (path, bytes, ranking.display_path(path)))
+        .collect();
+
+    let columns = view.columns;
+    let largest = shown.iter().map(|(_, bytes, _)| *bytes).max().unwrap_or(0);
+    let files_width = shown
+        .iter()
+        .map(|(path, _, _)| file_count_cell(ranking.file_counts, path).len())
+        .max()
+        .unwrap_or(0)
+        .max("files".len());
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+
+    if columns != [Column::Size] && !shown.is_empty() {
+        let mut header = " ".repeat(6);
+        for column in columns {
+            header += &match column {
+                Column::Size => format!(" {:>10}", "size"),
+                Column::Percent => format!(" {:>6}", "total"),
+                Column::Parent => format!(" {:>6}", "parent"),
+                Column::Bar => format!(" {:BAR_WIDTH$}", ""),
+                Column::Files => format!(" {:>files_width$}", "files"),
+                Column::Mtime => format!(" {:>8}", "modified"),
+                Column::Growth => format!(" {:>11}", "growth"),
+            };
+        }
+        println!("{}  {}", header.bright_black(), "path".bright_black());
+    }
 
-        display_count += 1;
+    for (index, (path, bytes, display_path)) in shown.into_iter().enumerate() {
+        let rank = index + 1;
 
-        // Add emoji based on size
-        let emoji = if bytes >= 1_000_000_000 {
+        // Add emoji based on size, in the base of the units shown
+        let (giga, mega) = (ranking.units.multiple(3), ranking.units.multiple(2));
+        let emoji = if bytes >= giga {
             "🔥"
         }
         // >= 1GB
-        else if bytes >= 100_000_000 {
+        else if bytes >= 100 * mega {
             "📦"
         }
         // >= 100MB
-        else if bytes >= 10_000_000 {
+        else if bytes >= 10 * mega {
             "📁"
         }
         // >= 10MB
@@ -184,49 +1329,155 @@ fn main() {
         }; // < 10MB
 
         // Color the rank number based on position
-        let rank_color = match display_count {
-            1 => format!("{display_count:2}.").bright_yellow().bold(),
-            2 => format!("{display_count:2}.").bright_magenta().bold(),
-            3 => format!("{display_count:2}.").bright_cyan().bold(),
-            _ => format!("{display_count:2}.").bright_white(),
+        let rank_color = match rank {
+            1 => format!("{rank:2}.").bright_yellow().bold(),
+            2 => format!("{rank:2}.").bright_magenta().bold(),
+            3 => format!("{rank:2}.").bright_cyan().bold(),
+            _ => format!("{rank:2}.").bright_white(),
         };
 
-        // Color the size based on magnitude
-        let size_color = if bytes >= 1_000_000_000 {
-            human.bright_red().bold()
-        }
-        // >= 1GB
-        else if bytes >= 100_000_000 {
-            human.bright_yellow().bold()
-        }
-        // >= 100MB
-        else if bytes >= 10_000_000 {
-            human.bright_green().bold()
+        let mut cells = String::new();
+        for column in columns {
+            let cell = match column {
+                Column::Size => format!("{:>10}", size_color(bytes, ranking.units)),
+                Column::Percent => percent(bytes, ranking.total).bright_cyan().to_string(),
+                Column::Parent => {
+                    let parent = path.parent().and_then(|parent| ranking.sizes.get(parent));
+                    match parent {
+                        Some(parent) => percent(bytes, *parent),
+                        None => format!("{:>6}", "-"),
+                    }
+                    .cyan()
+                    .to_string()
+                }
+                Column::Bar => bar(bytes, largest).bright_green().to_string(),
+                Column::Files => format!(
+                    "{:>files_width$}",
+                    file_count_cell(ranking.file_counts, path)
+                )
+                .bright_magenta()
+                .to_string(),
+                Column::Mtime => format!("{:>8}", modified_cell(ranking.modified, path, now))
+                    .bright_blue()
+                    .to_string(),
+                Column::Growth => {
+                    let change = ranking.growth.get(path).copied();
+                    let cell = format!("{:>11}", growth_cell(change, ranking.units));
+                    match change {
+                        Some(change) if change > 0 => cell.bright_red().to_string(),
+                        Some(change) if change < 0 => cell.bright_green().to_string(),
+                        _ => cell,
+                    }
+                }
+            };
+            cells.push(' ');
+            cells.push_str(&cell);
         }
-        // >= 10MB
-        else {
-            human.bright_blue()
-        }; // < 10MB
+
+        let compressed = ranking
+            .compressed
+            .get(path)
+            .map(|bytes| format!("  ({} compressed)", ranking.units.format(*bytes)))
+            .unwrap_or_default();
 
         println!(
-            "{} {} {:>10}  {}",
+            "{} {}{}  {}{}",
             emoji,
             rank_color,
-            size_color,
-            display_path.bright_white()
+            cells,
+            display_path.bright_white(),
+            compressed.bright_black()
         );
     }
+}
+
+/// A size in `units`, colored by magnitude.
+fn size_color(bytes: u64, units: SizeFormat) -> ColoredString {
+    let human = units.format(bytes);
+    let (giga, mega) = (units.multiple(3), units.multiple(2));
+    if bytes >= giga {
+        human.bright_red().bold()
+    }
+    // >= 1GB
+    else if bytes >= 100 * mega {
+        human.bright_yellow().bold()
+    }
+    // >= 100MB
+    else if bytes >= 10 * mega {
+        human.bright_green().bold()
+    }
+    // >= 10MB
+    else {
+        human.bright_blue()
+    } // < 10MB
+}
+
+/// `part` as a percentage of `whole`, right-aligned in 6 characters.
+fn percent(part: u64, whole: u64) -> String {
+    if whole == 0 {
+        return format!("{:>6}", "-");
+    }
+    format!("{:>5.1}%", part as f64 * 100.0 / whole as f64)
+}
+
+/// A bar of [`BAR_WIDTH`] characters, filled in eighths in proportion to
+/// `bytes` out of `largest`.
+fn bar(bytes: u64, largest: u64) -> String {
+    const PARTIAL: [char; 8] = [' ', '▏', '▎', '▍', '▌', '▋', '▊', '▉'];
+    let eighths = match largest {
+        0 => 0,
+        _ => (u128::from(bytes) * (BAR_WIDTH as u128 * 8) / u128::from(largest)) as usize,
+    };
+    let mut bar = "█".repeat(eighths / 8);
+    if eighths % 8 > 0 {
+        bar.push(PARTIAL[eighths % 8]);
+    }
+    let filled = bar.chars().count();
+    bar + &" ".repeat(BAR_WIDTH - filled)
+}
+
+/// The number of files below `path`, or `-` if it is not known.
+fn file_count_cell(file_counts: &HashMap<PathBuf, u64>, path: &Path) -> String {
+    file_counts
+        .get(path)
+        .map_or_else(|| "-".to_string(), |files| files.to_string())
+}
+
+/// How long ago the newest file below `path` was modified, or `-` if it
+/// is not known.
+fn modified_cell(modified: &HashMap<PathBuf, u64>, path: &Path, now: u64) -> String {
+    match modified.get(path) {
+        Some(&time) if time > 0 => format_age(Duration::from_secs(now.saturating_sub(time))),
+        _ => "-".to_string(),
+    }
+}
+
+/// A change in size with its sign, e.g. `+1.20 MB`, or `-` if it is not known.
+fn growth_cell(change: Option<i64>, units: SizeFormat) -> String {
+    match change {
+        Some(change) if change < 0 => format!("-{}", units.format(change.unsigned_abs())),
+        Some(change) => format!("+{}", units.format(change as u64)),
+        None => "-".to_string(),
+    }
+}
 
-    // Print summary
+/// Print the summary block for a scan labelled `label`.
+fn print_summary(
+    label: &str,
+    total_bytes: u64,
+    total_files: usize,
+    duration: Duration,
+    units: SizeFormat,
+) {
     println!(
         "📊 {} {}",
         "Summary of".bright_green().bold(),
-        base.display().to_string().bright_white().bold()
+        label.bright_white().bold()
     );
     println!(
         "💾 {}: {}",
         "Total file size".bright_cyan(),
-        format_size(base_size, DECIMAL).bright_yellow().bold()
+        units.format(total_bytes).bright_yellow().bold()
     );
     println!(
         "📋 {}: {}",
@@ -239,3 +1490,284 @@ fn main() {
         format!("{duration:.2?}").bright_yellow().bold()
     );
 }
+
+/// Print how much data is reached through several hard links, if any.
+fn print_hardlink_stats(stats: &HardlinkStats, units: SizeFormat) {
+    if stats.links == 0 {
+        return;
+    }
+    println!(
+        "🔗 {}: {} ({} files, {} links, {} shared)",
+        "Hardlinked data".bright_cyan(),
+        units.format(stats.bytes).bright_yellow().bold(),
+        stats.inodes,
+        stats.links,
+        units.format(stats.shared_bytes)
+    );
+}
+
+/// Print how many symlinks were followed, if any were met.
+fn print_symlink_stats(stats: &SymlinkStats) {
+    if *stats == SymlinkStats::default() {
+        return;
+    }
+    println!(
+        "↪️  {}: {} ({} broken, {} loops)",
+        "Symlinks followed".bright_cyan(),
+        stats.followed.to_string().bright_yellow().bold(),
+        stats.broken,
+        stats.loops
+    );
+}
+
+/// Describe why a scan stopped early.
+fn incomplete_message(reason: StopReason) -> &'static str {
+    match reason {
+        StopReason::Cancelled => "interrupted",
+        StopReason::TimedOut => "timed out",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format_age() {
+        assert_eq!(format_age(Duration::from_secs(42)), "42s");
+        assert_eq!(format_age(Duration::from_secs(15 * 60 + 5)), "15m");
+        assert_eq!(format_age(Duration::from_secs(3 * 3600)), "3h");
+        assert_eq!(format_age(Duration::from_secs(2 * 86400 + 1)), "2d");
+    }
+
+    #[test]
+    fn test_parse_duration() {
+        assert_eq!(parse_duration("30s"), Ok(Duration::from_secs(30)));
+        assert_eq!(parse_duration("45"), Ok(Duration::from_secs(45)));
+        assert_eq!(parse_duration("5m"), Ok(Duration::from_secs(300)));
+        assert_eq!(parse_duration("1h"), Ok(Duration::from_secs(3600)));
+        assert_eq!(parse_duration("250ms"), Ok(Duration::from_millis(250)));
+        assert_eq!(parse_duration("1.5s"), Ok(Duration::from_millis(1500)));
+        assert!(parse_duration("10x").is_err());
+        assert!(parse_duration("abc").is_err());
+    }
+
+    #[test]
+    fn test_parse_separator() {
+        assert_eq!(parse_separator("tab"), Ok('\t'));
+        assert_eq!(parse_separator("comma"), Ok(','));
+        assert_eq!(parse_separator(";"), Ok(';'));
+        assert!(parse_separator("ab").is_err());
+        assert!(parse_separator("").is_err());
+    }
+
+    #[test]
+    fn test_parse_column() {
+        assert_eq!(parse_column("size"), Ok(Column::Size));
+        assert_eq!(parse_column("parent"), Ok(Column::Parent));
+        assert_eq!(parse_column(" files"), Ok(Column::Files));
+        assert!(parse_column("owner").is_err());
+
+        let cli = Cli::try_parse_from(["rudu", "--columns", "percent,bar", "/tmp"]).unwrap();
+        assert_eq!(cli.columns, [Column::Percent, Column::Bar]);
+        let cli = Cli::try_parse_from(["rudu", "/tmp"]).unwrap();
+        assert_eq!(cli.columns, [Column::Size]);
+    }
+
+    #[test]
+    fn test_percent() {
+        assert_eq!(percent(1, 4), " 25.0%");
+        assert_eq!(percent(4, 4), "100.0%");
+        assert_eq!(percent(1, 3000), "  0.0%");
+        assert_eq!(percent(1, 0), "     -");
+    }
+
+    #[test]
+    fn test_bar_is_scaled_to_the_largest_entry() {
+        assert_eq!(bar(10, 10), "█".repeat(BAR_WIDTH));
+        assert_eq!(bar(5, 10), "█".repeat(10) + &" ".repeat(10));
+        // 1/16 of 20 characters is 1.25 characters: one full, two eighths
+        assert_eq!(bar(1, 16), "█▎".to_string() + &" ".repeat(18));
+        assert_eq!(bar(0, 10), " ".repeat(BAR_WIDTH));
+        assert_eq!(bar(0, 0), " ".repeat(BAR_WIDTH));
+        assert!(bar(u64::MAX, u64::MAX).chars().count() == BAR_WIDTH);
+    }
+
+    #[test]
+    fn test_parse_cache_size() {
+        // Bare numbers stay megabytes for compatibility
+        assert_eq!(parse_cache_size("500"), Ok(500_000_000));
+        assert_eq!(parse_cache_size("2GB"), Ok(2_000_000_000));
+        assert_eq!(parse_cache_size("512MiB"), Ok(512 << 20));
+        assert!(parse_cache_size("lots").is_err());
+    }
+
+    #[test]
+    fn test_size_units_are_exclusive() {
+        let units = |args: &[&str]| {
+            let cli = Cli::try_parse_from(["rudu"].iter().chain(args)).unwrap();
+            cli.units()
+        };
+        assert_eq!(units(&[]), SizeFormat::Decimal);
+        // --si only names the default
+        assert_eq!(units(&["--si"]), SizeFormat::Decimal);
+        assert_eq!(units(&["--binary"]), SizeFormat::Binary);
+        assert_eq!(units(&["--bytes"]), SizeFormat::Bytes);
+        assert_eq!(
+            units(&["-B", "M"]),
+            SizeFormat::Blocks(parse_block_size("M").unwrap())
+        );
+        assert!(Cli::try_parse_from(["rudu", "--binary", "--bytes"]).is_err());
+        assert!(Cli::try_parse_from(["rudu", "--si", "--block-size", "K"]).is_err());
+    }
+
+    #[test]
+    fn test_sort_key_adds_its_column() {
+        assert_eq!(parse_sort_key("mtime"), Ok(SortKey::Mtime));
+        assert!(parse_sort_key("owner").is_err());
+
+        let columns = |args: &[&str]| {
+            let cli = Cli::try_parse_from(["rudu"].iter().chain(args)).unwrap();
+            cli.shown_columns()
+        };
+        assert_eq!(columns(&["--sort", "name"]), [Column::Size]);
+        assert_eq!(columns(&["--sort", "files"]), [Column::Size, Column::Files]);
+        assert_eq!(
+            columns(&["--sort", "growth", "--columns", "growth,size"]),
+            [Column::Growth, Column::Size]
+        );
+        assert!(Cli::try_parse_from(["rudu", "--all", "-n", "5"]).is_err());
+        assert!(Cli::try_parse_from(["rudu", "--match", "("]).is_err());
+    }
+
+    #[test]
+    fn test_ranking_order_and_filters() {
+        let sizes = HashMap::from([
+            (PathBuf::from("/r"), 120),
+            (PathBuf::from("/r/logs"), 100),
+            (PathBuf::from("/r/logs/old"), 60),
+            (PathBuf::from("/r/src"), 20),
+            (PathBuf::from("/r/tmp"), 0),
+        ]);
+        let file_counts = HashMap::from([(PathBuf::from("/r/src"), 9)]);
+        let modified = HashMap::from([
+            (PathBuf::from("/r/logs"), 300),
+            (PathBuf::from("/r/src"), 100),
+        ]);
+        let ranking = Ranking {
+            entries: ranked_entries(&sizes, &[&PathBuf::from("/r")]),
+            sizes: &sizes,
+            file_counts: &file_counts,
+            modified: &modified,
+            growth: &HashMap::new(),
+            compressed: &HashMap::new(),
+            base: Some(Path::new("/r")),
+            total: 120,
+            units: SizeFormat::Decimal,
+        };
+        let order = |sort: SortKey, reverse: bool| {
+            let mut entries: Vec<(&PathBuf, u64)> =
+                ranking.entries.iter().map(|(p, b)| (p, *b)).collect();
+            entries.sort_by(|a, b| ranking.compare(sort, reverse, *a, *b));
+            entries
+                .into_iter()
+                .map(|(path, _)| ranking.display_path(path))
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(
+            order(SortKey::Size, false),
+            ["logs", "logs/old", "src", "tmp"]
+        );
+        assert_eq!(
+            order(SortKey::Size, true),
+            ["tmp", "src", "logs/old", "logs"]
+        );
+        assert_eq!(
+            order(SortKey::Name, true),
+            ["tmp", "src", "logs/old", "logs"]
+        );
+        // Unknown keys come last, ties are broken by path
+        assert_eq!(
+            order(SortKey::Files, false),
+            ["src", "logs", "logs/old", "tmp"]
+        );
+        assert_eq!(
+            order(SortKey::Mtime, false),
+            ["logs", "src", "logs/old", "tmp"]
+        );
+
+        let view = View {
+            columns: &[Column::Size],
+            sort: SortKey::Size,
+            reverse: false,
+            min_size: Some(10),
+            max_size: Some(80),
+            pattern: None,
+            limit: None,
+        };
+        assert!(view.fits(10) && view.fits(80));
+        assert!(!view.fits(9) && !view.fits(81));
+    }
+
+    #[test]
+    fn test_growth() {
+        let previous = CacheEntry::new(
+            Path::new("/r"),
+            HashMap::from([(PathBuf::from("/r"), 100), (PathBuf::from("/r/a"), 100)]),
+            1,
+            Default::default(),
+        );
+        let sizes = HashMap::from([
+            (PathBuf::from("/r"), 130),
+            (PathBuf::from("/r/a"), 80),
+            (PathBuf::from("/r/new"), 50),
+        ]);
+        let growth = growth_since(&sizes, &previous);
+        assert_eq!(growth[Path::new("/r")], 30);
+        assert_eq!(growth[Path::new("/r/a")], -20);
+        assert_eq!(growth[Path::new("/r/new")], 50);
+
+        assert_eq!(growth_cell(Some(1_500), SizeFormat::Decimal), "+1.50 kB");
+        assert_eq!(growth_cell(Some(-20), SizeFormat::Bytes), "-20");
+        assert_eq!(growth_cell(Some(0), SizeFormat::Bytes), "+0");
+        assert_eq!(growth_cell(None, SizeFormat::Bytes), "-");
+    }
+
+    #[test]
+    fn test_modified_cell() {
+        let modified = HashMap::from([(PathBuf::from("/r/a"), 1_000), (PathBuf::from("/r/b"), 0)]);
+        assert_eq!(
+            modified_cell(&modified, Path::new("/r/a"), 1_000 + 7200),
+            "2h"
+        );
+        assert_eq!(modified_cell(&modified, Path::new("/r/b"), 5_000), "-");
+        assert_eq!(modified_cell(&modified, Path::new("/r/c"), 5_000), "-");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_resolve_arg_follows_links_to_archives() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = resolve_path(dir.path());
+        let archive = root.join("backup.tar.gz");
+        fs::write(&archive, "").unwrap();
+        fs::create_dir(root.join("data")).unwrap();
+        std::os::unix::fs::symlink(&archive, root.join("latest")).unwrap();
+        std::os::unix::fs::symlink(root.join("data"), root.join("current")).unwrap();
+
+        assert_eq!(resolve_arg(&root.join("latest")), archive);
+        // Links to directories keep the name given
+        assert_eq!(resolve_arg(&root.join("current")), root.join("current"));
+    }
+
+    #[test]
+    fn test_parse_hardlink_policy() {
+        assert_eq!(parse_hardlink_policy("once"), Ok(HardlinkPolicy::Once));
+        assert_eq!(
+            parse_hardlink_policy("proportional"),
+            Ok(HardlinkPolicy::Proportional)
+        );
+        assert!(parse_hardlink_policy("twice").is_err());
+    }
+}
@@ -0,0 +1,296 @@
+use crate::units::SizeFormat;
+use colored::*;
+use std::io::{IsTerminal, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Default time between progress updates.
+pub const DEFAULT_PROGRESS_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Shortest time between redraws of a [`ProgressDisplay`], a little under
+/// [`DEFAULT_PROGRESS_INTERVAL`] so that a single scan reporting at the
+/// default rate is never skipped.
+const REDRAW_INTERVAL: Duration = Duration::from_millis(80);
+
+/// Snapshot of a running scan, delivered to progress callbacks.
+#[derive(Clone, Debug, Default)]
+pub struct ScanProgress {
+    /// Regular files seen so far
+    pub files: u64,
+    /// Directories read so far
+    pub dirs: u64,
+    /// Bytes counted so far, after hardlink deduplication
+    pub bytes: u64,
+    /// Directory most recently picked up by a worker, if known
+    pub current_dir: Option<PathBuf>,
+    /// Time since the scan started
+    pub elapsed: Duration,
+}
+
+impl ScanProgress {
+    /// Average number of files processed per second since the scan started.
+    pub fn files_per_second(&self) -> f64 {
+        let secs = self.elapsed.as_secs_f64();
+        if secs > 0.0 {
+            self.files as f64 / secs
+        } else {
+            0.0
+        }
+    }
+}
+
+/// Counters updated by scanner workers and sampled by the progress reporter.
+#[derive(Default)]
+pub(crate) struct ProgressCounters {
+    files: AtomicU64,
+    dirs: AtomicU64,
+    bytes: AtomicU64,
+    current_dir: Mutex<Option<PathBuf>>,
+}
+
+impl ProgressCounters {
+    /// Record a finished directory containing `files` files totalling `bytes`.
+    pub fn add_dir(&self, files: u64, bytes: u64) {
+        self.dirs.fetch_add(1, Ordering::Relaxed);
+        self.files.fetch_add(files, Ordering::Relaxed);
+        self.bytes.fetch_add(bytes, Ordering::Relaxed);
+    }
+
+    /// Record `files` files totalling `bytes` outside of a directory walk.
+    pub fn add_files(&self, files: u64, bytes: u64) {
+        self.files.fetch_add(files, Ordering::Relaxed);
+        self.bytes.fetch_add(bytes, Ordering::Relaxed);
+    }
+
+    /// Note the directory a worker is about to read. Skipped if another
+    /// worker holds the lock, since only an approximate value is needed.
+    pub fn set_current_dir(&self, path: &Path) {
+        if let Ok(mut current) = self.current_dir.try_lock() {
+            *current = Some(path.to_path_buf());
+        }
+    }
+
+    pub fn snapshot(&self, started: Instant) -> ScanProgress {
+        ScanProgress {
+            files: self.files.load(Ordering::Relaxed),
+            dirs: self.dirs.load(Ordering::Relaxed),
+            bytes: self.bytes.load(Ordering::Relaxed),
+            current_dir: self
+                .current_dir
+                .lock()
+                .map(|current| current.clone())
+                .unwrap_or(None),
+            elapsed: started.elapsed(),
+        }
+    }
+}
+
+/// Single-line progress display written to stderr.
+///
+/// The display is a no-op when stderr is not a terminal, so it is safe to use
+/// unconditionally in pipelines and scripts.
+///
+/// Nothing else in the library writes to the terminal; the display only draws
+/// when a caller creates one and forwards progress to it.
+///
+/// # Example
+///
+/// ```rust
+/// use std::path::Path;
+/// use rudu::{ProgressDisplay, Scanner};
+///
+/// let display = ProgressDisplay::new(Path::new("/tmp"));
+/// let result = Scanner::new("/tmp")
+///     .on_progress(|progress| display.update(progress))
+///     .scan();
+/// display.finish();
+/// println!("{} bytes", result.tree.size(result.tree.root()));
+/// ```
+pub struct ProgressDisplay {
+    base: PathBuf,
+    enabled: bool,
+    units: SizeFormat,
+    /// Snapshots from every root and when the line was last drawn, shared
+    /// by all scanners reporting to this display
+    state: Mutex<DisplayState>,
+}
+
+/// What a [`ProgressDisplay`] has been told so far.
+#[derive(Default)]
+struct DisplayState {
+    /// Latest snapshot from each concurrently scanned root
+    roots: Vec<ScanProgress>,
+    last_drawn: Option<Instant>,
+}
+
+impl DisplayState {
+    /// Record the latest snapshot for the root at `index`.
+    fn record(&mut self, index: usize, progress: &ScanProgress) {
+        if self.roots.len() <= index {
+            self.roots.resize(index + 1, ScanProgress::default());
+        }
+        self.roots[index] = progress.clone();
+    }
+
+    /// Whether the line is due for a redraw at `now`; if so, it counts as drawn.
+    fn redraw_due(&mut self, now: Instant) -> bool {
+        let due = self
+            .last_drawn
+            .map_or(true, |last| now.duration_since(last) >= REDRAW_INTERVAL);
+        if due {
+            self.last_drawn = Some(now);
+        }
+        due
+    }
+
+    /// Totals over every root, showing `current_dir` as the current directory.
+    fn combined(&self, current_dir: Option<PathBuf>) -> ScanProgress {
+        ScanProgress {
+            files: self.roots.iter().map(|p| p.files).sum(),
+            dirs: self.roots.iter().map(|p| p.dirs).sum(),
+            bytes: self.roots.iter().map(|p| p.bytes).sum(),
+            current_dir,
+            elapsed: self
+                .roots
+                .iter()
+                .map(|p| p.elapsed)
+                .max()
+                .unwrap_or_default(),
+        }
+    }
+}
+
+impl ProgressDisplay {
+    /// Create a display for a scan of `base`, enabled only if stderr is a terminal.
+    ///
+    /// Current directories are shown relative to `base`; pass an empty path
+    /// to show them in full.
+    pub fn new(base: &Path) -> Self {
+        ProgressDisplay {
+            base: base.to_path_buf(),
+            enabled: std::io::stderr().is_terminal(),
+            units: SizeFormat::default(),
+            state: Mutex::new(DisplayState::default()),
+        }
+    }
+
+    /// Show the bytes scanned so far in `units` (default: decimal).
+    pub fn units(mut self, units: SizeFormat) -> Self {
+        self.units = units;
+        self
+    }
+
+    /// Whether updates are actually drawn.
+    pub fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    /// Redraw the progress line, unless it was drawn moments ago.
+    pub fn update(&self, progress: &ScanProgress) {
+        self.update_root(0, progress);
+    }
+
+    /// Record the latest snapshot for the root at `index` and redraw a line
+    /// combining every root seen so far.
+    ///
+    /// Use this when several scanners share one display. However many roots
+    /// report, the line is redrawn at most about once per
+    /// [`DEFAULT_PROGRESS_INTERVAL`], so concurrent scans do not flicker.
+    pub fn update_root(&self, index: usize, progress: &ScanProgress) {
+        if !self.enabled {
+            return;
+        }
+
+        // Drawing under the lock keeps redraws from different roots in order
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        state.record(index, progress);
+        if state.redraw_due(Instant::now()) {
+            self.draw(&state.combined(progress.current_dir.clone()));
+        }
+    }
+
+    fn draw(&self, progress: &ScanProgress) {
+        let current = progress
+            .current_dir
+            .as_deref()
+            .map(|dir| {
+                let relative = dir.strip_prefix(&self.base).unwrap_or(dir);
+                truncate_start(&relative.display().to_string(), 40)
+            })
+            .unwrap_or_default();
+
+        let mut stderr = std::io::stderr().lock();
+        let _ = write!(
+            stderr,
+            "\r\x1b[2K⏳ {} files, {} dirs, {} ({:.0} files/s, {:.1?}) {}",
+            progress.files.to_string().bright_yellow(),
+            progress.dirs.to_string().bright_yellow(),
+            self.units.format(progress.bytes).bright_yellow(),
+            progress.files_per_second(),
+            progress.elapsed,
+            current.bright_black()
+        );
+        let _ = stderr.flush();
+    }
+
+    /// Clear the progress line so normal output starts on a clean line.
+    pub fn finish(&self) {
+        if self.enabled {
+            let mut stderr = std::io::stderr().lock();
+            let _ = write!(stderr, "\r\x1b[2K");
+            let _ = stderr.flush();
+        }
+    }
+}
+
+/// Keep the last `max` characters of `text`, marking the cut with an ellipsis.
+fn truncate_start(text: &str, max: usize) -> String {
+    let count = text.chars().count();
+    if count <= max {
+        text.to_string()
+    } else {
+        let tail: String = text.chars().skip(count - max + 1).collect();
+        format!("…{tail}")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_truncate_start() {
+        assert_eq!(truncate_start("short", 10), "short");
+        assert_eq!(truncate_start("a/very/long/path", 8), "…ng/path");
+    }
+
+    #[test]
+    fn test_redraws_are_throttled_across_roots() {
+        let mut state = DisplayState::default();
+        let start = Instant::now();
+        let progress = |files| ScanProgress {
+            files,
+            ..Default::default()
+        };
+
+        state.record(0, &progress(10));
+        assert!(state.redraw_due(start));
+        state.record(1, &progress(5));
+        assert!(!state.redraw_due(start + REDRAW_INTERVAL / 2));
+        assert!(state.redraw_due(start + DEFAULT_PROGRESS_INTERVAL));
+        assert_eq!(state.combined(None).files, 15);
+    }
+
+    #[test]
+    fn test_files_per_second() {
+        let progress = ScanProgress {
+            files: 500,
+            elapsed: Duration::from_secs(2),
+            ..Default::default()
+        };
+        assert_eq!(progress.files_per_second(), 250.0);
+        assert_eq!(ScanProgress::default().files_per_second(), 0.0);
+    }
+}
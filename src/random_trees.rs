@@ -0,0 +1,396 @@
+//! Property-style tests: scans of seeded random trees are checked against a
+//! straightforward reference implementation.
+//!
+//! Trees mix directories, files (some empty, some large and sparse on disk),
+//! hard links, symlinks to files, directories, themselves and nowhere, and,
+//! in memory only, entries that cannot be read. Seeds are fixed, so a failure
+//! names the seed that reproduces it.
+
+use crate::filesystem::MemoryFs;
+use crate::hardlinks::{HardlinkPolicy, HardlinkStats};
+use crate::symlinks::SymlinkStats;
+use crate::{ScanResult, Scanner};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::path::{Path, PathBuf};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Kind {
+    Dir,
+    /// A path to inode `ino`; several paths to one inode are hard links
+    File {
+        ino: usize,
+    },
+    Symlink(Target),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Target {
+    /// Another entry, never a symlink
+    Entry(usize),
+    Missing,
+    Itself,
+}
+
+#[derive(Debug)]
+struct Entry {
+    /// Path relative to the root; empty for the root itself
+    path: PathBuf,
+    kind: Kind,
+    /// Listing or stat'ing the entry fails with a permission error
+    denied: bool,
+}
+
+/// A random tree, independent of where it is materialized.
+#[derive(Debug)]
+struct Model {
+    entries: Vec<Entry>,
+    /// Size of each inode
+    lens: Vec<u64>,
+}
+
+/// Sizes at or above this are written as sparse files on disk.
+const SPARSE_LEN: u64 = 1 << 20;
+
+impl Model {
+    /// Generate a tree of `size` entries below the root. With `denials`,
+    /// a few directories and files are made unreadable.
+    fn generate(seed: u64, size: usize, denials: bool) -> Self {
+        let mut rng = fastrand::Rng::with_seed(seed);
+        let mut model = Model {
+            entries: vec![Entry {
+                path: PathBuf::new(),
+                kind: Kind::Dir,
+                denied: false,
+            }],
+            lens: Vec::new(),
+        };
+
+        for i in 0..size {
+            let dirs: Vec<usize> = model.indices(|kind| kind == Kind::Dir);
+            let parent = dirs[rng.usize(..dirs.len())];
+            let path = model.entries[parent].path.join(format!("e{i}"));
+            let files = model.indices(|kind| matches!(kind, Kind::File { .. }));
+            let targets = model.indices(|kind| !matches!(kind, Kind::Symlink(_)));
+
+            let kind = match rng.u8(..100) {
+                0..=24 => Kind::Dir,
+                25..=74 => {
+                    model.lens.push(match rng.u8(..10) {
+                        0 => 0,
+                        1 => rng.u64(SPARSE_LEN..SPARSE_LEN * 64),
+                        _ => rng.u64(1..5_000),
+                    });
+                    Kind::File {
+                        ino: model.lens.len() - 1,
+                    }
+                }
+                75..=84 if !files.is_empty() => model.entries[files[rng.usize(..files.len())]].kind,
+                roll => Kind::Symlink(match roll % 4 {
+                    0 => Target::Missing,
+                    1 => Target::Itself,
+                    _ => Target::Entry(targets[rng.usize(..targets.len())]),
+                }),
+            };
+            model.entries.push(Entry {
+                path,
+                kind,
+                denied: false,
+            });
+        }
+
+        if denials {
+            for entry in model.entries.iter_mut().skip(1) {
+                if !matches!(entry.kind, Kind::Symlink(_)) && rng.u8(..100) < 5 {
+                    entry.denied = true;
+                }
+            }
+        }
+        model
+    }
+
+    fn indices(&self, keep: impl Fn(Kind) -> bool) -> Vec<usize> {
+        (0..self.entries.len())
+            .filter(|&index| keep(self.entries[index].kind))
+            .collect()
+    }
+
+    fn full_path(&self, root: &Path, index: usize) -> PathBuf {
+        match index {
+            0 => root.to_path_buf(),
+            _ => root.join(&self.entries[index].path),
+        }
+    }
+
+    fn memory_fs(&self, root: &Path) -> MemoryFs {
+        let mut fs = MemoryFs::new();
+        let mut first_path: HashMap<usize, PathBuf> = HashMap::new();
+        for (index, entry) in self.entries.iter().enumerate() {
+            let path = self.full_path(root, index);
+            match entry.kind {
+                Kind::Dir => fs.add_dir(&path),
+                Kind::File { ino } => match first_path.get(&ino) {
+                    Some(target) => fs.add_hardlink(&path, target),
+                    None => {
+                        fs.add_file(&path, self.lens[ino]);
+                        first_path.insert(ino, path);
+                    }
+                },
+                Kind::Symlink(target) => {
+                    let target = self.target_path(root, index, target);
+                    fs.add_symlink_to(&path, target);
+                }
+            }
+        }
+        for (index, entry) in self.entries.iter().enumerate() {
+            if entry.denied {
+                fs.deny(self.full_path(root, index));
+            }
+        }
+        fs
+    }
+
+    /// Write the tree to disk under `root`. Denied entries are not modelled,
+    /// since tests may run as root.
+    #[cfg(unix)]
+    fn write_to(&self, root: &Path) {
+        use std::fs;
+        use std::io::Write;
+
+        let mut first_path: HashMap<usize, PathBuf> = HashMap::new();
+        for (index, entry) in self.entries.iter().enumerate() {
+            let path = self.full_path(root, index);
+            match entry.kind {
+                Kind::Dir => fs::create_dir_all(&path).unwrap(),
+                Kind::File { ino } => match first_path.get(&ino) {
+                    Some(target) => fs::hard_link(target, &path).unwrap(),
+                    None => {
+                        let mut file = fs::File::create(&path).unwrap();
+                        let len = self.lens[ino];
+                        if len >= SPARSE_LEN {
+                            file.set_len(len).unwrap();
+                        } else {
+                            file.write_all(&vec![b'x'; len as usize]).unwrap();
+                        }
+                        first_path.insert(ino, path);
+                    }
+                },
+                Kind::Symlink(target) => {
+                    let target = self.target_path(root, index, target);
+                    std::os::unix::fs::symlink(target, &path).unwrap();
+                }
+            }
+        }
+    }
+
+    fn target_path(&self, root: &Path, index: usize, target: Target) -> PathBuf {
+        match target {
+            Target::Entry(target) => self.full_path(root, target),
+            Target::Missing => root.join("missing"),
+            Target::Itself => self.full_path(root, index),
+        }
+    }
+
+    /// Whether no directory above the entry is denied.
+    fn reachable(&self, index: usize) -> bool {
+        let path = &self.entries[index].path;
+        !self.entries.iter().any(|other| {
+            other.denied
+                && other.kind == Kind::Dir
+                && path.starts_with(&other.path)
+                && *path != other.path
+        })
+    }
+
+    /// What a scan of the tree at `root` should find, computed entry by
+    /// entry without any of the scanner's machinery.
+    fn expected(&self, root: &Path, policy: HardlinkPolicy, follow: bool) -> Expected {
+        let mut expected = Expected::default();
+        let mut paths_by_ino: BTreeMap<usize, Vec<PathBuf>> = BTreeMap::new();
+
+        for (index, entry) in self.entries.iter().enumerate() {
+            if !self.reachable(index) {
+                continue;
+            }
+            let path = self.full_path(root, index);
+            match entry.kind {
+                // Unreadable directories stay in the tree, empty
+                Kind::Dir => {
+                    expected.sizes.insert(path, 0);
+                    expected.errors += u64::from(entry.denied);
+                }
+                Kind::File { .. } if entry.denied => expected.errors += 1,
+                Kind::File { ino } => {
+                    expected.files += 1;
+                    paths_by_ino.entry(ino).or_default().push(path);
+                }
+                Kind::Symlink(_) if !follow => {}
+                Kind::Symlink(target) => match target {
+                    Target::Missing => expected.symlinks.broken += 1,
+                    Target::Itself => expected.symlinks.loops += 1,
+                    Target::Entry(target) => {
+                        let target_entry = &self.entries[target];
+                        let parent = entry.path.parent().unwrap_or(Path::new(""));
+                        if target_entry.kind == Kind::Dir && parent.starts_with(&target_entry.path)
+                        {
+                            expected.symlinks.loops += 1;
+                        } else {
+                            expected.symlinks.followed += 1;
+                            // A followed file counts as a file, its bytes
+                            // stay with its real path
+                            if target_entry.kind != Kind::Dir {
+                                expected.files += 1;
+                            }
+                        }
+                    }
+                },
+            }
+        }
+
+        for (ino, mut paths) in paths_by_ino {
+            paths.sort();
+            let len = self.lens[ino];
+            let links = paths.len() as u64;
+            if links > 1 {
+                expected.hardlinks.inodes += 1;
+                expected.hardlinks.links += links;
+                expected.hardlinks.bytes += len;
+                expected.hardlinks.shared_bytes += len * (links - 1);
+            }
+            for (index, path) in paths.iter().enumerate() {
+                let index = index as u64;
+                let bytes = match policy {
+                    HardlinkPolicy::All => len,
+                    HardlinkPolicy::Once => u64::from(index == 0) * len,
+                    HardlinkPolicy::Proportional => len / links + u64::from(index < len % links),
+                };
+                for dir in path
+                    .ancestors()
+                    .skip(1)
+                    .take_while(|dir| dir.starts_with(root))
+                {
+                    *expected.sizes.get_mut(dir).unwrap() += bytes;
+                }
+            }
+        }
+        expected
+    }
+}
+
+#[derive(Debug, Default, PartialEq)]
+struct Expected {
+    sizes: HashMap<PathBuf, u64>,
+    files: u64,
+    errors: u64,
+    hardlinks: HardlinkStats,
+    symlinks: SymlinkStats,
+}
+
+impl From<&ScanResult> for Expected {
+    fn from(result: &ScanResult) -> Self {
+        Expected {
+            sizes: result.tree.to_size_map(),
+            files: result.total_files() as u64,
+            errors: result.errors,
+            hardlinks: result.hardlink_stats,
+            symlinks: result.symlinks,
+        }
+    }
+}
+
+/// Compare a scan with the reference, naming the seed on failure.
+fn check(seed: u64, result: &ScanResult, expected: &Expected) {
+    let actual = Expected::from(result);
+    if actual == *expected {
+        return;
+    }
+    let mut wrong: Vec<_> = expected
+        .sizes
+        .iter()
+        .filter(|(path, bytes)| actual.sizes.get(*path) != Some(bytes))
+        .map(|(path, bytes)| (path, *bytes, actual.sizes.get(path).copied()))
+        .collect();
+    wrong.sort();
+    let extra: HashSet<_> = actual
+        .sizes
+        .keys()
+        .filter(|path| !expected.sizes.contains_key(*path))
+        .collect();
+    panic!(
+        "seed {seed}: scan differs from the reference\n\
+         directories (path, expected, actual): {wrong:?}\n\
+         unexpected directories: {extra:?}\n\
+         expected: files {}, errors {}, {:?}, {:?}\n\
+         actual:   files {}, errors {}, {:?}, {:?}",
+        expected.files,
+        expected.errors,
+        expected.hardlinks,
+        expected.symlinks,
+        actual.files,
+        actual.errors,
+        actual.hardlinks,
+        actual.symlinks,
+    );
+}
+
+#[test]
+fn test_random_memory_trees_match_reference() {
+    let root = Path::new("/r");
+    for seed in 0..100 {
+        let model = Model::generate(seed, 150, true);
+        for policy in [
+            HardlinkPolicy::Once,
+            HardlinkPolicy::All,
+            HardlinkPolicy::Proportional,
+        ] {
+            let result = Scanner::new(root)
+                .filesystem(model.memory_fs(root))
+                .hardlinks(policy)
+                .scan();
+            check(seed, &result, &model.expected(root, policy, false));
+        }
+    }
+}
+
+#[test]
+fn test_random_memory_trees_following_symlinks() {
+    let root = Path::new("/r");
+    for seed in 100..150 {
+        // Following a symlink into a denied directory would reach entries the
+        // walk cannot, which the reference does not model
+        let model = Model::generate(seed, 150, false);
+        let result = Scanner::new(root)
+            .filesystem(model.memory_fs(root))
+            .follow_symlinks(true)
+            .scan();
+        check(
+            seed,
+            &result,
+            &model.expected(root, HardlinkPolicy::Once, true),
+        );
+    }
+}
+
+#[cfg(unix)]
+#[test]
+fn test_random_trees_on_disk_match_reference() {
+    for seed in 200..210 {
+        let model = Model::generate(seed, 120, false);
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path().join("r");
+        model.write_to(&root);
+
+        // Sparse files count with their apparent size
+        let result = Scanner::new(&root).scan();
+        check(
+            seed,
+            &result,
+            &model.expected(&root, HardlinkPolicy::Once, false),
+        );
+        let result = Scanner::new(&root).follow_symlinks(true).scan();
+        check(
+            seed,
+            &result,
+            &model.expected(&root, HardlinkPolicy::Once, true),
+        );
+    }
+}
@@ -0,0 +1,1215 @@
+use crate::archive::{scan_archive, ArchiveKind, ArchiveScan};
+use crate::cancel::{CancellationToken, StopReason};
+use crate::error::Error;
+use crate::files_from::normalize_path;
+use crate::filesystem::{is_symlink_loop, FileKind, FileSystem, Metadata, RealFs};
+use crate::hardlinks::{self, HardlinkPolicy, HardlinkStats, Link};
+use crate::progress::{ProgressCounters, ScanProgress, DEFAULT_PROGRESS_INTERVAL};
+use crate::symlinks::{Follow, PendingDir, SymlinkStats};
+use crate::tree::{DirRecord, DirTree};
+use crate::visit::{Aggregator, FileEntry, Visitor};
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicU32, AtomicU64, AtomicU8, Ordering};
+use std::sync::{mpsc, Mutex, OnceLock};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Number of listed files each parallel task stats at a time.
+const FILE_CHUNK: usize = 1024;
+
+type ProgressCallback<'a> = Box<dyn Fn(&ScanProgress) + Send + Sync + 'a>;
+type ErrorCallback<'a> = Box<dyn Fn(&Error) + Send + Sync + 'a>;
+
+/// Result of a scan, possibly cut short by cancellation or a timeout.
+pub struct ScanResult {
+    /// Directory tree with aggregated sizes
+    pub tree: DirTree,
+    /// Wall-clock time the scan took
+    pub duration: Duration,
+    /// Why the scan stopped early, or `None` if every directory was read
+    pub stopped: Option<StopReason>,
+    /// Directories and files that could not be read
+    pub errors: u64,
+    /// Size of every multiply-linked inode counted, keyed by `(device, inode)`
+    ///
+    /// Used to avoid double counting when combining scans of separate roots.
+    /// Always empty on platforms without inode numbers, and under
+    /// [`HardlinkPolicy::All`], which never deduplicates links.
+    pub hardlinks: HashMap<(u64, u64), u64>,
+    /// Directories holding the links counted for each inode in `hardlinks`,
+    /// with the bytes counted in each
+    pub hardlink_dirs: HashMap<(u64, u64), Vec<(PathBuf, u64)>>,
+    /// Files reached through more than one hard link
+    pub hardlink_stats: HardlinkStats,
+    /// Symlinks followed, broken and looping, when [`Scanner::follow_symlinks`]
+    /// is enabled
+    pub symlinks: SymlinkStats,
+    /// Archives read as virtual directories, either because the base is an
+    /// archive or because [`Scanner::archives`] is enabled
+    ///
+    /// Their contents are not rolled up into `tree`, which counts each
+    /// archive by its size on disk.
+    pub archives: Vec<ArchiveScan>,
+}
+
+impl ScanResult {
+    /// Whether every directory was read. Incomplete results undercount sizes.
+    pub fn is_complete(&self) -> bool {
+        self.stopped.is_none()
+    }
+
+    /// Total number of files counted under the scanned root.
+    pub fn total_files(&self) -> usize {
+        self.tree.file_count(self.tree.root()) as usize
+    }
+}
+
+/// Scanner settings that change the sizes a scan reports, recorded with
+/// cached results.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(default)]
+#[non_exhaustive]
+pub struct ScanOptions {
+    /// Archive contents are listed, see [`Scanner::archives`]
+    pub archives: bool,
+    /// How hardlinked files are counted, see [`Scanner::hardlinks`]
+    pub hardlinks: HardlinkPolicy,
+    /// Symlinks found in the tree are followed, see [`Scanner::follow_symlinks`]
+    pub follow_symlinks: bool,
+}
+
+impl fmt::Display for ScanOptions {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut enabled: Vec<String> = Vec::new();
+        if self.archives {
+            enabled.push("archives".to_string());
+        }
+        if self.hardlinks != HardlinkPolicy::default() {
+            enabled.push(format!("hardlinks={}", self.hardlinks));
+        }
+        if self.follow_symlinks {
+            enabled.push("follow-symlinks".to_string());
+        }
+        if enabled.is_empty() {
+            write!(f, "defaults")
+        } else {
+            write!(f, "{}", enabled.join(", "))
+        }
+    }
+}
+
+/// Configurable parallel directory scanner.
+///
+/// # Example
+///
+/// ```rust
+/// use std::sync::mpsc;
+/// use rudu::Scanner;
+///
+/// // Progress snapshots can be forwarded to a channel from the callback
+/// let (tx, rx) = mpsc::channel();
+/// let tx = std::sync::Mutex::new(tx);
+/// let result = Scanner::new("/tmp")
+///     .on_progress(move |progress| {
+///         let _ = tx.lock().unwrap().send(progress.clone());
+///     })
+///     .scan();
+/// drop(rx);
+/// println!("{} files in {:?}", result.total_files(), result.duration);
+/// ```
+pub struct Scanner<'a> {
+    base: PathBuf,
+    fs: Box<dyn FileSystem + 'a>,
+    progress: Option<ProgressCallback<'a>>,
+    on_error: Option<ErrorCallback<'a>>,
+    progress_interval: Duration,
+    pub(crate) cancel: Option<CancellationToken>,
+    timeout: Option<Duration>,
+    archives: bool,
+    hardlinks: HardlinkPolicy,
+    follow_symlinks: bool,
+}
+
+impl<'a> Scanner<'a> {
+    /// Create a scanner rooted at `base`.
+    pub fn new(base: impl Into<PathBuf>) -> Self {
+        Scanner {
+            base: base.into(),
+            fs: Box::new(RealFs),
+            progress: None,
+            on_error: None,
+            progress_interval: DEFAULT_PROGRESS_INTERVAL,
+            cancel: None,
+            timeout: None,
+            archives: false,
+            hardlinks: HardlinkPolicy::default(),
+            follow_symlinks: false,
+        }
+    }
+
+    /// Root directory this scanner will traverse.
+    pub fn base(&self) -> &Path {
+        &self.base
+    }
+
+    /// Read directories and metadata from `fs` instead of the local filesystem.
+    ///
+    /// Archives are always read from the local filesystem.
+    pub fn filesystem(mut self, fs: impl FileSystem + 'a) -> Self {
+        self.fs = Box::new(fs);
+        self
+    }
+
+    /// Call `callback` with a progress snapshot periodically while scanning.
+    ///
+    /// The callback runs on a dedicated reporter thread, at most once per
+    /// [`Scanner::progress_interval`], and once more with the final totals
+    /// when the walk finishes.
+    pub fn on_progress(mut self, callback: impl Fn(&ScanProgress) + Send + Sync + 'a) -> Self {
+        self.progress = Some(Box::new(callback));
+        self
+    }
+
+    /// Call `callback` with an [`Error::Walk`] for every directory or file
+    /// that cannot be read.
+    ///
+    /// Unreadable entries are always counted in [`ScanResult::errors`]; the
+    /// callback runs on the worker thread that hit the error.
+    pub fn on_error(mut self, callback: impl Fn(&Error) + Send + Sync + 'a) -> Self {
+        self.on_error = Some(Box::new(callback));
+        self
+    }
+
+    /// Set the minimum time between progress callbacks.
+    pub fn progress_interval(mut self, interval: Duration) -> Self {
+        self.progress_interval = interval;
+        self
+    }
+
+    /// Stop the scan early when `token` is cancelled.
+    ///
+    /// Directories already being read are finished; the rest are skipped and
+    /// the partial result is returned with [`ScanResult::stopped`] set.
+    pub fn cancel_token(mut self, token: CancellationToken) -> Self {
+        self.cancel = Some(token);
+        self
+    }
+
+    /// Stop the scan once `timeout` has elapsed, returning the partial result.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// Also read `.tar`, `.tar.gz`, `.tgz` and `.zip` files found during the
+    /// scan as virtual directories, reported in [`ScanResult::archives`].
+    pub fn archives(mut self, enabled: bool) -> Self {
+        self.archives = enabled;
+        self
+    }
+
+    /// Choose how files with several hard links are counted (default:
+    /// [`HardlinkPolicy::Once`]).
+    ///
+    /// Only links inside the scanned tree are taken into account.
+    pub fn hardlinks(mut self, policy: HardlinkPolicy) -> Self {
+        self.hardlinks = policy;
+        self
+    }
+
+    /// Follow symlinks found during the scan, counting what they lead to
+    /// as if it were in the link's directory.
+    ///
+    /// A file or directory reached through several paths is counted once,
+    /// at its real path if that is inside the scan, otherwise at the first
+    /// symlink in sorted order. Symlinks leading back to a directory above
+    /// them are not entered. Broken and looping symlinks are passed to
+    /// [`Scanner::on_error`] as [`Error::BrokenSymlink`] and
+    /// [`Error::SymlinkLoop`] and counted in [`ScanResult::symlinks`], not
+    /// in [`ScanResult::errors`]. The base is always followed.
+    pub fn follow_symlinks(mut self, enabled: bool) -> Self {
+        self.follow_symlinks = enabled;
+        self
+    }
+
+    /// The settings of this scanner that affect its results.
+    pub fn options(&self) -> ScanOptions {
+        ScanOptions {
+            archives: self.archives,
+            hardlinks: self.hardlinks,
+            follow_symlinks: self.follow_symlinks,
+        }
+    }
+
+    /// Run the scan, blocking until every directory has been read or the
+    /// scan is cancelled or times out.
+    ///
+    /// If the base is an archive file rather than a directory, its members
+    /// are scanned instead, with uncompressed sizes in [`ScanResult::tree`].
+    pub fn scan(&self) -> ScanResult {
+        self.walk(None)
+    }
+
+    /// Run the scan like [`Scanner::scan`], also feeding every regular file
+    /// to `aggregator` and returning its merged output.
+    ///
+    /// Files are visited on the worker threads as they are found, after
+    /// hardlink deduplication. Subdirectories rejected by
+    /// [`Aggregator::enter_dir`] are not read. Archive contents are not visited.
+    pub fn scan_with<A: Aggregator>(&self, aggregator: &A) -> (ScanResult, A::Output) {
+        // One accumulator per worker, so visiting a file never contends
+        let shards: Vec<Mutex<Option<A::Output>>> =
+            (0..scan_threads()).map(|_| Mutex::new(None)).collect();
+        let file = |entry: &FileEntry<'_>| {
+            let shard = rayon::current_thread_index().unwrap_or(0) % shards.len();
+            let mut acc = shards[shard].lock().unwrap_or_else(|e| e.into_inner());
+            aggregator.visit(acc.get_or_insert_with(|| aggregator.init()), entry);
+        };
+        let enter_dir = |path: &Path| aggregator.enter_dir(path);
+
+        let result = self.walk(Some(Visitor {
+            file: &file,
+            enter_dir: &enter_dir,
+        }));
+        let output = shards
+            .into_iter()
+            .filter_map(|shard| shard.into_inner().unwrap_or_else(|e| e.into_inner()))
+            .reduce(|a, b| aggregator.merge(a, b))
+            .unwrap_or_else(|| aggregator.init());
+        (result, output)
+    }
+
+    fn walk(&self, visitor: Option<Visitor<'_>>) -> ScanResult {
+        let started = Instant::now();
+        if ArchiveKind::from_path(&self.base).is_some()
+            && self
+                .fs
+                .symlink_metadata(&self.base)
+                .is_ok_and(|metadata| metadata.is_file())
+        {
+            return self.scan_archive_base(started);
+        }
+
+        // Every directory becomes its own task on a work-stealing pool, so both
+        // `read_dir` and `metadata` calls are spread across all worker threads
+        let root = DirTask {
+            id: 0,
+            parent: 0,
+            path: self.base.clone(),
+        };
+        let (mut ctx, ()) = self.run(started, visitor, |ctx| {
+            rayon::scope(|scope| walk_dir(scope, root, ctx));
+            // Symlinked directories are entered once everything before them
+            // has been walked, so their real paths claim them first
+            while ctx.follow.is_some() {
+                let tasks = ctx.follow_pending_dirs();
+                if tasks.is_empty() {
+                    break;
+                }
+                rayon::scope(|scope| {
+                    for task in tasks {
+                        scope.spawn(move |scope| walk_dir(scope, task, ctx));
+                    }
+                });
+            }
+        });
+
+        let mut records: Vec<DirRecord> = std::mem::take(&mut ctx.records)
+            .into_iter()
+            .flat_map(|shard| shard.into_inner().unwrap_or_else(|e| e.into_inner()))
+            .collect();
+        records.sort_unstable_by_key(|record| record.id);
+
+        // Hardlinked files are counted once every link is known, so the
+        // outcome does not depend on which worker reached a link first
+        let resolved = ctx.resolve_links(self.hardlinks);
+        for (link, counted_bytes) in resolved.links {
+            debug_assert_eq!(records[link.dir as usize].id, link.dir);
+            records[link.dir as usize].bytes += counted_bytes;
+            if let Some(visitor) = ctx.visitor {
+                (visitor.file)(&FileEntry {
+                    path: &link.path,
+                    metadata: &link.metadata,
+                    counted_bytes,
+                });
+            }
+        }
+
+        let tree = DirTree::from_records(self.base.clone(), records);
+        let mut result = ctx.finish(tree, started);
+        result.hardlinks = resolved.inodes;
+        result.hardlink_dirs = resolved.dirs;
+        result.hardlink_stats = resolved.stats;
+        result
+    }
+
+    fn scan_archive_base(&self, started: Instant) -> ScanResult {
+        let (errors, archives) = match scan_archive(&self.base) {
+            Ok(scan) => (0, vec![scan]),
+            Err(e) => {
+                if let Some(on_error) = &self.on_error {
+                    on_error(&Error::walk(&self.base, e));
+                }
+                (1, Vec::new())
+            }
+        };
+        let tree = archives
+            .first()
+            .map_or_else(|| DirTree::new(self.base.clone()), |scan| scan.tree.clone());
+        ScanResult {
+            tree,
+            duration: started.elapsed(),
+            stopped: None,
+            errors,
+            hardlinks: HashMap::new(),
+            hardlink_dirs: HashMap::new(),
+            hardlink_stats: HardlinkStats::default(),
+            symlinks: SymlinkStats::default(),
+            archives,
+        }
+    }
+
+    /// Aggregate an explicit list of files instead of walking the base directory.
+    ///
+    /// Files are stat'ed in parallel on the scan pool and their sizes rolled
+    /// up into their ancestor directories, up to the scanner's base. Relative
+    /// paths are resolved against the current directory. Entries that are
+    /// not regular files are ignored; files outside the base or that cannot
+    /// be read are counted in [`ScanResult::errors`].
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use std::path::PathBuf;
+    /// use rudu::Scanner;
+    ///
+    /// let files = vec![PathBuf::from("/etc/hostname"), PathBuf::from("/etc/hosts")];
+    /// let result = Scanner::new("/etc").scan_files(&files);
+    /// println!("{} bytes", result.tree.size(result.tree.root()));
+    /// ```
+    pub fn scan_files(&self, files: &[PathBuf]) -> ScanResult {
+        let started = Instant::now();
+        let cwd = std::env::current_dir().unwrap_or_default();
+
+        let (mut ctx, mut per_dir) = self.run(started, None, |ctx| {
+            files
+                .par_chunks(FILE_CHUNK)
+                .map(|chunk| stat_chunk(chunk, &cwd, &self.base, ctx))
+                .reduce(HashMap::new, |mut merged, part| {
+                    for (dir, (bytes, count, modified)) in part {
+                        let entry = merged.entry(dir).or_insert((0, 0, 0));
+                        entry.0 += bytes;
+                        entry.1 += count;
+                        entry.2 = entry.2.max(modified);
+                    }
+                    merged
+                })
+        });
+
+        let resolved = ctx.resolve_links(self.hardlinks);
+        for (link, counted_bytes) in resolved.links {
+            if let Some(parent) = link.path.parent() {
+                per_dir.entry(parent.to_path_buf()).or_insert((0, 0, 0)).0 += counted_bytes;
+            }
+        }
+
+        let mut tree = DirTree::new(self.base.clone());
+        for (dir, (bytes, count, modified)) in per_dir {
+            if let Some(id) = tree.insert_dir(&dir) {
+                tree.add_size(id, bytes, count);
+                tree.add_modified(id, modified);
+            }
+        }
+        let mut result = ctx.finish(tree, started);
+        result.hardlinks = resolved.inodes;
+        result.hardlink_dirs = resolved.dirs;
+        result.hardlink_stats = resolved.stats;
+        result
+    }
+
+    /// Run `work` on the scan pool while reporting progress, returning the
+    /// shared context and the work's output once it has finished.
+    fn run<'s, T: Send>(
+        &'s self,
+        started: Instant,
+        visitor: Option<Visitor<'s>>,
+        work: impl FnOnce(&WalkContext) -> T + Send,
+    ) -> (WalkContext<'s>, T) {
+        let pool = scan_pool();
+        let threads = pool
+            .as_ref()
+            .map_or_else(rayon::current_num_threads, |pool| {
+                pool.current_num_threads()
+            });
+
+        let ctx = WalkContext {
+            fs: self.fs.as_ref(),
+            visitor,
+            on_error: self.on_error.as_deref(),
+            next_id: AtomicU32::new(1),
+            records: (0..threads).map(|_| Mutex::new(Vec::new())).collect(),
+            links: (0..threads).map(|_| Mutex::new(Vec::new())).collect(),
+            follow: self.follow_symlinks.then(|| Follow::new(threads)),
+            errors: AtomicU64::new(0),
+            progress: self.progress.as_ref().map(|_| ProgressCounters::default()),
+            cancel: self.cancel.clone(),
+            deadline: self.timeout.map(|timeout| started + timeout),
+            stopped: AtomicBool::new(false),
+            stop_reason: AtomicU8::new(0),
+            archives: self.archives.then(|| Mutex::new(Vec::new())),
+        };
+
+        let output = thread::scope(|s| {
+            let (done_tx, done_rx) = mpsc::channel::<()>();
+            if let (Some(callback), Some(counters)) = (&self.progress, &ctx.progress) {
+                let interval = self.progress_interval;
+                s.spawn(move || {
+                    while let Err(mpsc::RecvTimeoutError::Timeout) = done_rx.recv_timeout(interval)
+                    {
+                        callback(&counters.snapshot(started));
+                    }
+                    // Always deliver the final totals once the walk is over
+                    callback(&counters.snapshot(started));
+                });
+            }
+
+            let output = match &pool {
+                Some(pool) => pool.install(|| work(&ctx)),
+                None => work(&ctx),
+            };
+            drop(done_tx);
+            output
+        });
+
+        (ctx, output)
+    }
+}
+
+/// Shared state for a single parallel directory traversal.
+struct WalkContext<'f> {
+    fs: &'f dyn FileSystem,
+    /// Custom aggregation hooks from [`Scanner::scan_with`]
+    visitor: Option<Visitor<'f>>,
+    on_error: Option<&'f (dyn Fn(&Error) + Send + Sync)>,
+    /// Next node id to hand out; ids are allocated by the parent task so a
+    /// child's id is always greater than its parent's
+    next_id: AtomicU32,
+    /// Finished directories, sharded per worker thread to avoid contention
+    records: Vec<Mutex<Vec<DirRecord>>>,
+    /// Multiply-linked files found so far, counted once the walk is over
+    links: Vec<Mutex<Vec<Link>>>,
+    /// Directories claimed and symlinks met, only allocated when following symlinks
+    follow: Option<Follow>,
+    /// Entries that could not be read
+    errors: AtomicU64,
+    /// Live counters, only allocated when someone is listening
+    progress: Option<ProgressCounters>,
+    cancel: Option<CancellationToken>,
+    deadline: Option<Instant>,
+    /// Latched once the scan has been asked to stop
+    stopped: AtomicBool,
+    /// First [`StopReason`] observed, encoded by [`WalkContext::stop`]
+    stop_reason: AtomicU8,
+    /// Archives read so far, only allocated when descending into archives
+    archives: Option<Mutex<Vec<ArchiveScan>>>,
+}
+
+impl WalkContext<'_> {
+    /// Count an entry that could not be read and pass it to the error hook.
+    fn report(&self, path: &Path, source: io::Error) {
+        self.errors.fetch_add(1, Ordering::Relaxed);
+        if let Some(on_error) = self.on_error {
+            on_error(&Error::walk(path, source));
+        }
+    }
+
+    /// Report a symlink that cannot be followed. Only errors other than
+    /// broken links and loops count as unreadable entries.
+    fn report_symlink(&self, path: &Path, source: io::Error) {
+        let Some(follow) = &self.follow else {
+            return;
+        };
+        let error = if source.kind() == io::ErrorKind::NotFound {
+            follow.broken();
+            Error::BrokenSymlink {
+                path: path.to_path_buf(),
+                source,
+            }
+        } else if is_symlink_loop(&source) {
+            follow.looped();
+            Error::SymlinkLoop {
+                path: path.to_path_buf(),
+            }
+        } else {
+            return self.report(path, source);
+        };
+        if let Some(on_error) = self.on_error {
+            on_error(&error);
+        }
+    }
+
+    /// Follow the symlink at `path` in the directory `dir`, returning the
+    /// metadata of a file it leads to. Directories are queued for
+    /// [`WalkContext::follow_pending_dirs`].
+    fn follow_symlink(&self, dir: u32, path: PathBuf) -> Option<Metadata> {
+        let follow = self.follow.as_ref()?;
+        match self.fs.metadata(&path) {
+            Ok(metadata) if metadata.is_file() => {
+                follow.followed();
+                Some(metadata)
+            }
+            Ok(metadata) if metadata.kind == FileKind::Dir => {
+                if !self
+                    .visitor
+                    .is_some_and(|visitor| !(visitor.enter_dir)(&path))
+                {
+                    follow.defer_dir(PendingDir {
+                        parent: dir,
+                        path,
+                        key: (metadata.dev, metadata.ino),
+                    });
+                }
+                None
+            }
+            Ok(_) => None,
+            Err(e) => {
+                self.report_symlink(&path, e);
+                None
+            }
+        }
+    }
+
+    /// Whether the directory of `task` was already entered through another
+    /// path, when following symlinks.
+    fn already_entered(&self, task: &DirTask) -> bool {
+        let Some(follow) = &self.follow else {
+            return false;
+        };
+        // An unreadable directory is reported when listing it
+        match self.fs.metadata(&task.path) {
+            Ok(metadata) => follow
+                .claim((metadata.dev, metadata.ino), task.id)
+                .is_some(),
+            Err(_) => false,
+        }
+    }
+
+    /// Turn the symlinked directories found by the last walk into tasks,
+    /// skipping directories already entered and reporting loops.
+    fn follow_pending_dirs(&self) -> Vec<DirTask> {
+        let Some(follow) = &self.follow else {
+            return Vec::new();
+        };
+        let pending = follow.take_pending();
+        if pending.is_empty() || self.should_stop() {
+            return Vec::new();
+        }
+
+        // Every directory handed out so far has been recorded by now
+        let mut parents = vec![0; self.next_id.load(Ordering::Relaxed) as usize];
+        for shard in &self.records {
+            for record in shard.lock().unwrap_or_else(|e| e.into_inner()).iter() {
+                parents[record.id as usize] = record.parent;
+            }
+        }
+        let is_ancestor = |ancestor: u32, mut id: u32| loop {
+            if id == ancestor {
+                return true;
+            }
+            if id == 0 {
+                return false;
+            }
+            id = parents[id as usize];
+        };
+
+        let mut tasks = Vec::new();
+        for dir in pending {
+            let id = self.next_id.load(Ordering::Relaxed);
+            match follow.claim(dir.key, id) {
+                Some(owner) if is_ancestor(owner, dir.parent) => {
+                    follow.looped();
+                    if let Some(on_error) = self.on_error {
+                        on_error(&Error::SymlinkLoop {
+                            path: dir.path.clone(),
+                        });
+                    }
+                }
+                Some(_) => follow.followed(),
+                None => {
+                    follow.followed();
+                    self.next_id.fetch_add(1, Ordering::Relaxed);
+                    tasks.push(DirTask {
+                        id,
+                        parent: dir.parent,
+                        path: dir.path,
+                    });
+                }
+            }
+        }
+        tasks
+    }
+
+    /// Hold back a file with several links, or reached through a symlink,
+    /// until every path to it has been found.
+    ///
+    /// Returns `false` for files with a single link, which are counted right away.
+    fn defer_link(&self, dir: u32, path: &Path, metadata: &Metadata, via_symlink: bool) -> bool {
+        if metadata.nlink <= 1 && !via_symlink {
+            if let Some(follow) = &self.follow {
+                follow.count_file(metadata);
+            }
+            return false;
+        }
+        let shard = rayon::current_thread_index().unwrap_or(0) % self.links.len();
+        self.links[shard]
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .push(Link {
+                dir,
+                path: path.to_path_buf(),
+                metadata: *metadata,
+                via_symlink,
+            });
+        true
+    }
+
+    /// Decide what the links held back by [`WalkContext::defer_link`] count.
+    fn resolve_links(&mut self, policy: HardlinkPolicy) -> hardlinks::Resolved {
+        let links = std::mem::take(&mut self.links)
+            .into_iter()
+            .flat_map(|shard| shard.into_inner().unwrap_or_else(|e| e.into_inner()))
+            .collect();
+        let counted = match &mut self.follow {
+            Some(follow) => follow.counted_files(),
+            None => HashSet::new(),
+        };
+        hardlinks::resolve(links, policy, &counted)
+    }
+
+    /// Aggregate `tree` and package it with everything the workers collected.
+    fn finish(self, mut tree: DirTree, started: Instant) -> ScanResult {
+        tree.aggregate();
+        ScanResult {
+            tree,
+            duration: started.elapsed(),
+            stopped: self.stop_reason(),
+            errors: self.errors.load(Ordering::Relaxed),
+            hardlinks: HashMap::new(),
+            hardlink_dirs: HashMap::new(),
+            hardlink_stats: HardlinkStats::default(),
+            symlinks: self.follow.as_ref().map(Follow::stats).unwrap_or_default(),
+            archives: self
+                .archives
+                .map(|archives| archives.into_inner().unwrap_or_else(|e| e.into_inner()))
+                .unwrap_or_default(),
+        }
+    }
+
+    /// Check whether workers should stop, latching the reason the first time.
+    fn should_stop(&self) -> bool {
+        if self.stopped.load(Ordering::Relaxed) {
+            return true;
+        }
+        if self.cancel.as_ref().is_some_and(|c| c.is_cancelled()) {
+            self.stop(StopReason::Cancelled);
+            return true;
+        }
+        if self
+            .deadline
+            .is_some_and(|deadline| Instant::now() >= deadline)
+        {
+            self.stop(StopReason::TimedOut);
+            return true;
+        }
+        false
+    }
+
+    fn stop(&self, reason: StopReason) {
+        let code = match reason {
+            StopReason::Cancelled => 1,
+            StopReason::TimedOut => 2,
+        };
+        let _ = self
+            .stop_reason
+            .compare_exchange(0, code, Ordering::Relaxed, Ordering::Relaxed);
+        self.stopped.store(true, Ordering::Relaxed);
+    }
+
+    fn stop_reason(&self) -> Option<StopReason> {
+        match self.stop_reason.load(Ordering::Relaxed) {
+            1 => Some(StopReason::Cancelled),
+            2 => Some(StopReason::TimedOut),
+            _ => None,
+        }
+    }
+}
+
+/// A directory waiting to be read, with its preallocated node id.
+struct DirTask {
+    id: u32,
+    parent: u32,
+    path: PathBuf,
+}
+
+/// The thread pool used for scanning, built on first use and shared by every
+/// scan, so that scanning several roots at once does not multiply threads.
+///
+/// Directory traversal is dominated by syscalls rather than CPU work, so the
+/// pool is sized above the core count to keep the disk queue busy. Returns
+/// `None` if the pool cannot be created, in which case the global rayon pool
+/// is used instead.
+fn scan_pool() -> Option<&'static rayon::ThreadPool> {
+    static POOL: OnceLock<Option<rayon::ThreadPool>> = OnceLock::new();
+    POOL.get_or_init(|| {
+        rayon::ThreadPoolBuilder::new()
+            .num_threads(scan_threads())
+            .thread_name(|i| format!("rudu-scan-{i}"))
+            .build()
+            .ok()
+    })
+    .as_ref()
+}
+
+/// Number of worker threads in the scan pool.
+fn scan_threads() -> usize {
+    (num_cpus::get() * 2).clamp(4, 64)
+}
+
+/// Read one directory, spawning a task for each subdirectory and summing the
+/// sizes of the regular files it contains directly.
+///
+/// Every task records its directory exactly once, even if it cannot be read,
+/// was already entered through a symlink or the scan has been stopped, so
+/// node ids stay contiguous. Symlinks are only followed when enabled. Files
+/// with several hard links are held back and counted after the walk.
+fn walk_dir<'s>(scope: &rayon::Scope<'s>, task: DirTask, ctx: &'s WalkContext<'_>) {
+    let mut dir_bytes = 0u64;
+    let mut dir_files = 0u64;
+    let mut dir_modified = 0u64;
+
+    if let Some(progress) = &ctx.progress {
+        progress.set_current_dir(&task.path);
+    }
+
+    // Unreadable directories are kept in the tree with a size of zero
+    let read_dir = if ctx.should_stop() || ctx.already_entered(&task) {
+        None
+    } else {
+        match ctx.fs.read_dir(&task.path) {
+            Ok(read_dir) => Some(read_dir),
+            Err(e) => {
+                ctx.report(&task.path, e);
+                None
+            }
+        }
+    };
+    if let Some(read_dir) = read_dir {
+        for entry in read_dir {
+            if ctx.stopped.load(Ordering::Relaxed) {
+                break;
+            }
+
+            match entry.kind {
+                FileKind::Dir => {
+                    if ctx
+                        .visitor
+                        .is_some_and(|visitor| !(visitor.enter_dir)(&entry.path))
+                    {
+                        continue;
+                    }
+                    let child = DirTask {
+                        id: ctx.next_id.fetch_add(1, Ordering::Relaxed),
+                        parent: task.id,
+                        path: entry.path,
+                    };
+                    scope.spawn(move |scope| walk_dir(scope, child, ctx));
+                }
+                FileKind::File => {
+                    let metadata = entry
+                        .metadata
+                        .unwrap_or_else(|| ctx.fs.symlink_metadata(&entry.path));
+                    match metadata {
+                        Ok(metadata) => {
+                            dir_files += 1;
+                            dir_modified = dir_modified.max(metadata.modified);
+                            if !ctx.defer_link(task.id, &entry.path, &metadata, false) {
+                                dir_bytes += metadata.len;
+                                if let Some(visitor) = ctx.visitor {
+                                    (visitor.file)(&FileEntry {
+                                        path: &entry.path,
+                                        metadata: &metadata,
+                                        counted_bytes: metadata.len,
+                                    });
+                                }
+                            }
+                            if ctx.archives.is_some()
+                                && ArchiveKind::from_path(&entry.path).is_some()
+                            {
+                                let path = entry.path;
+                                scope.spawn(move |_| read_archive(path, ctx));
+                            }
+                        }
+                        Err(e) => {
+                            ctx.report(&entry.path, e);
+                        }
+                    }
+                }
+                FileKind::Symlink => {
+                    let path = entry.path;
+                    if let Some(metadata) = ctx.follow_symlink(task.id, path.clone()) {
+                        dir_files += 1;
+                        dir_modified = dir_modified.max(metadata.modified);
+                        ctx.defer_link(task.id, &path, &metadata, true);
+                    }
+                }
+                FileKind::Other => {}
+            }
+        }
+    }
+
+    if let Some(progress) = &ctx.progress {
+        progress.add_dir(dir_files, dir_bytes);
+    }
+
+    let record = DirRecord {
+        id: task.id,
+        parent: task.parent,
+        name: task.path.file_name().unwrap_or_default().to_os_string(),
+        bytes: dir_bytes,
+        files: dir_files,
+        modified: dir_modified,
+    };
+    let shard = rayon::current_thread_index().unwrap_or(0) % ctx.records.len();
+    ctx.records[shard]
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .push(record);
+}
+
+/// Read an archive found during a walk, counting unreadable ones as errors.
+fn read_archive(path: PathBuf, ctx: &WalkContext<'_>) {
+    if ctx.should_stop() {
+        return;
+    }
+    match scan_archive(&path) {
+        Ok(scan) => {
+            if let Some(archives) = &ctx.archives {
+                archives
+                    .lock()
+                    .unwrap_or_else(|e| e.into_inner())
+                    .push(scan);
+            }
+        }
+        Err(e) => {
+            ctx.report(&path, e);
+        }
+    }
+}
+
+/// Stat one chunk of a file list, returning bytes, file counts and the newest
+/// modification time per parent directory.
+fn stat_chunk(
+    chunk: &[PathBuf],
+    cwd: &Path,
+    base: &Path,
+    ctx: &WalkContext<'_>,
+) -> HashMap<PathBuf, (u64, u64, u64)> {
+    let mut per_dir: HashMap<PathBuf, (u64, u64, u64)> = HashMap::new();
+    if ctx.should_stop() {
+        return per_dir;
+    }
+
+    let (mut chunk_files, mut chunk_bytes) = (0, 0);
+    for file in chunk {
+        let path = normalize_path(&cwd.join(file));
+        let parent = match path.parent() {
+            Some(parent) if parent.starts_with(base) => parent,
+            _ => {
+                let outside =
+                    io::Error::new(io::ErrorKind::InvalidInput, "not inside the base directory");
+                ctx.report(&path, outside);
+                continue;
+            }
+        };
+
+        let metadata = match ctx.fs.symlink_metadata(&path) {
+            Ok(metadata) if metadata.kind == FileKind::Symlink && ctx.follow.is_some() => {
+                match ctx.follow_symlink(0, path.clone()) {
+                    Some(target) => Ok((target, true)),
+                    None => continue,
+                }
+            }
+            other => other.map(|metadata| (metadata, false)),
+        };
+        match metadata {
+            Ok((metadata, via_symlink)) if metadata.is_file() => {
+                let bytes = if ctx.defer_link(0, &path, &metadata, via_symlink) {
+                    0
+                } else {
+                    metadata.len
+                };
+                let entry = per_dir.entry(parent.to_path_buf()).or_insert((0, 0, 0));
+                entry.0 += bytes;
+                entry.1 += 1;
+                entry.2 = entry.2.max(metadata.modified);
+                chunk_files += 1;
+                chunk_bytes += bytes;
+            }
+            // Directories and symlinks in the list contribute nothing themselves
+            Ok(_) => {}
+            Err(e) => {
+                ctx.report(&path, e);
+            }
+        }
+    }
+
+    if let Some(progress) = &ctx.progress {
+        if let Some(dir) = chunk.last().and_then(|file| file.parent()) {
+            progress.set_current_dir(dir);
+        }
+        progress.add_files(chunk_files, chunk_bytes);
+    }
+    per_dir
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::filesystem::MemoryFs;
+    use std::fs;
+    use std::sync::atomic::AtomicUsize;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_progress_callback_receives_snapshots() {
+        let dir = tempdir().unwrap();
+        for i in 0..20 {
+            let sub = dir.path().join(format!("d{i}"));
+            fs::create_dir(&sub).unwrap();
+            fs::write(sub.join("f"), "data").unwrap();
+        }
+
+        let calls = AtomicUsize::new(0);
+        let last = Mutex::new(ScanProgress::default());
+        let result = Scanner::new(dir.path())
+            .progress_interval(Duration::from_millis(1))
+            .on_progress(|progress| {
+                calls.fetch_add(1, Ordering::Relaxed);
+                *last.lock().unwrap() = progress.clone();
+            })
+            .scan();
+
+        assert!(calls.load(Ordering::Relaxed) >= 1);
+        let last = last.into_inner().unwrap();
+        assert_eq!(last.files, 20);
+        assert_eq!(last.dirs, 21);
+        assert_eq!(last.bytes, 80);
+        assert_eq!(result.total_files(), 20);
+        assert_eq!(result.tree.size(result.tree.root()), 80);
+        assert!(result.is_complete());
+    }
+
+    #[test]
+    fn test_cancelled_scan_is_partial() {
+        let dir = tempdir().unwrap();
+        fs::create_dir(dir.path().join("sub")).unwrap();
+        fs::write(dir.path().join("sub/file"), "data").unwrap();
+
+        let token = CancellationToken::new();
+        token.cancel();
+        let result = Scanner::new(dir.path()).cancel_token(token).scan();
+
+        assert_eq!(result.stopped, Some(StopReason::Cancelled));
+        assert_eq!(result.tree.len(), 1);
+        assert_eq!(result.total_files(), 0);
+    }
+
+    #[test]
+    fn test_timeout_stops_scan() {
+        let dir = tempdir().unwrap();
+        fs::write(dir.path().join("file"), "data").unwrap();
+
+        let result = Scanner::new(dir.path()).timeout(Duration::ZERO).scan();
+        assert_eq!(result.stopped, Some(StopReason::TimedOut));
+
+        let result = Scanner::new(dir.path())
+            .timeout(Duration::from_secs(60))
+            .scan();
+        assert!(result.is_complete());
+        assert_eq!(result.total_files(), 1);
+    }
+
+    #[test]
+    fn test_archives_are_reported_separately() {
+        use std::io::Write;
+
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("data.zip");
+        let mut writer = zip::ZipWriter::new(fs::File::create(&path).unwrap());
+        writer
+            .start_file("a/b.txt", zip::write::SimpleFileOptions::default())
+            .unwrap();
+        writer.write_all(&[b'x'; 5000]).unwrap();
+        writer.finish().unwrap();
+        let on_disk = fs::metadata(&path).unwrap().len();
+
+        let result = Scanner::new(dir.path()).scan();
+        assert!(result.archives.is_empty());
+
+        let result = Scanner::new(dir.path()).archives(true).scan();
+        // Directory totals still count the archive by its size on disk
+        assert_eq!(result.tree.size(result.tree.root()), on_disk);
+        assert_eq!(result.archives.len(), 1);
+        let archive = &result.archives[0];
+        let a = archive.tree.lookup(&path.join("a")).unwrap();
+        assert_eq!(archive.tree.size(a), 5000);
+
+        // An archive given as the base is scanned in place of a directory
+        let result = Scanner::new(&path).scan();
+        assert_eq!(result.tree.size(result.tree.root()), 5000);
+        assert_eq!(result.total_files(), 1);
+    }
+
+    #[test]
+    fn test_hardlink_policies() {
+        let sizes = |policy| {
+            let mut memory = MemoryFs::new();
+            memory.add_file("/h/b/file", 90);
+            memory.add_hardlink("/h/a/link", "/h/b/file");
+            memory.add_hardlink("/h/c/link", "/h/b/file");
+            memory.add_file("/h/c/plain", 5);
+            let result = Scanner::new("/h")
+                .filesystem(memory)
+                .hardlinks(policy)
+                .scan();
+            let size = |dir: &str| {
+                result
+                    .tree
+                    .size(result.tree.lookup(Path::new(dir)).unwrap())
+            };
+            assert_eq!(result.total_files(), 4);
+            assert_eq!(
+                result.hardlink_stats,
+                HardlinkStats {
+                    inodes: 1,
+                    links: 3,
+                    bytes: 90,
+                    shared_bytes: 180,
+                }
+            );
+            (size("/h"), size("/h/a"), size("/h/b"), size("/h/c"))
+        };
+
+        // The first path in sorted order carries the inode, however the
+        // workers happened to reach it
+        for _ in 0..10 {
+            assert_eq!(sizes(HardlinkPolicy::Once), (95, 90, 0, 5));
+        }
+        assert_eq!(sizes(HardlinkPolicy::All), (275, 90, 90, 95));
+        assert_eq!(sizes(HardlinkPolicy::Proportional), (95, 30, 30, 35));
+    }
+
+    #[test]
+    fn test_follow_symlinks() {
+        let memory = || {
+            let mut memory = MemoryFs::new();
+            memory.add_file("/s/real/data/file", 100);
+            memory.add_file("/s/real/other", 10);
+            memory.add_file("/ext/big", 1000);
+            memory.add_symlink_to("/s/farm/data", "../real/data");
+            memory.add_symlink_to("/s/farm/ext", "/ext");
+            memory.add_symlink_to("/s/farm/ext2", "/ext");
+            memory.add_symlink_to("/s/farm/big", "/ext/big");
+            memory.add_symlink_to("/s/farm/up", "..");
+            memory.add_symlink_to("/s/farm/self", "self");
+            memory.add_symlink("/s/farm/broken");
+            memory
+        };
+
+        let result = Scanner::new("/s").filesystem(memory()).scan();
+        assert_eq!(result.tree.size(result.tree.root()), 110);
+        assert_eq!(result.symlinks, SymlinkStats::default());
+
+        let reported = Mutex::new(Vec::new());
+        let result = Scanner::new("/s")
+            .filesystem(memory())
+            .follow_symlinks(true)
+            .on_error(|error| reported.lock().unwrap().push(error.to_string()))
+            .scan();
+        let size = |dir: &str| {
+            result
+                .tree
+                .lookup(Path::new(dir))
+                .map(|id| result.tree.size(id))
+        };
+
+        // Every target is counted once: at its real path inside the scan,
+        // otherwise at the first symlink to it
+        assert_eq!(size("/s"), Some(1110));
+        assert_eq!(size("/s/farm"), Some(1000));
+        assert_eq!(size("/s/farm/ext"), Some(1000));
+        assert_eq!(size("/s/farm/data"), None);
+        assert_eq!(size("/s/farm/ext2"), None);
+        assert_eq!(size("/s/farm/up"), None);
+        assert_eq!(
+            result.symlinks,
+            SymlinkStats {
+                followed: 4,
+                broken: 1,
+                loops: 2,
+            }
+        );
+        assert_eq!(result.errors, 0);
+        let mut reported = reported.into_inner().unwrap();
+        reported.sort();
+        assert_eq!(reported.len(), 3);
+        assert!(reported[0].starts_with("broken symlink '/s/farm/broken'"));
+        assert_eq!(reported[1], "symlink loop at '/s/farm/self'");
+        assert_eq!(reported[2], "symlink loop at '/s/farm/up'");
+    }
+
+    #[test]
+    fn test_memory_filesystem_with_unreadable_entries() {
+        let mut memory = MemoryFs::new();
+        memory.add_file("/m/a/one", 100);
+        memory.add_file("/m/a/two", 50);
+        memory.add_file("/m/locked/hidden", 1000);
+        memory.add_symlink("/m/a/link");
+        memory.deny("/m/locked");
+        memory.add_file("/m/b/unstatable", 7);
+        memory.deny("/m/b/unstatable");
+
+        let failed = Mutex::new(Vec::new());
+        let result = Scanner::new("/m")
+            .filesystem(memory)
+            .on_error(|error| {
+                if let Error::Walk { path, source } = error {
+                    failed.lock().unwrap().push((path.clone(), source.kind()));
+                }
+            })
+            .scan();
+        assert_eq!(result.errors, 2);
+        let mut failed = failed.into_inner().unwrap();
+        failed.sort();
+        assert_eq!(
+            failed,
+            vec![
+                (
+                    PathBuf::from("/m/b/unstatable"),
+                    io::ErrorKind::PermissionDenied
+                ),
+                (PathBuf::from("/m/locked"), io::ErrorKind::PermissionDenied),
+            ]
+        );
+        assert_eq!(result.total_files(), 2);
+        assert_eq!(result.tree.size(result.tree.root()), 150);
+        // Unreadable directories stay in the tree with a size of zero
+        let locked = result.tree.lookup(Path::new("/m/locked")).unwrap();
+        assert_eq!(result.tree.size(locked), 0);
+    }
+}
@@ -0,0 +1,131 @@
+use crate::filesystem::Metadata;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+
+/// Symlinks met by a scan that follows them, see
+/// [`Scanner::follow_symlinks`](crate::Scanner::follow_symlinks).
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SymlinkStats {
+    /// Symlinks leading to a file or directory, including ones whose target
+    /// was already counted through another path
+    pub followed: u64,
+    /// Symlinks leading to nothing
+    pub broken: u64,
+    /// Symlinks leading back to a directory above them, or through too many
+    /// other symlinks
+    pub loops: u64,
+}
+
+impl SymlinkStats {
+    /// Add the counts of `other`, e.g. from a separately scanned subtree.
+    pub fn merge(&mut self, other: &SymlinkStats) {
+        self.followed += other.followed;
+        self.broken += other.broken;
+        self.loops += other.loops;
+    }
+}
+
+/// A symlink to a directory, entered once the walk that found it is over.
+pub(crate) struct PendingDir {
+    /// Node id of the directory holding the symlink
+    pub parent: u32,
+    pub path: PathBuf,
+    /// `(device, inode)` of the target directory
+    pub key: (u64, u64),
+}
+
+/// Shared state of a walk that follows symlinks.
+///
+/// Every directory entered claims its `(device, inode)`, so a directory
+/// reached again through a symlink is skipped and counted only once.
+pub(crate) struct Follow {
+    /// Claimed directories with the node id of their first path, sharded by key
+    dirs: Vec<Mutex<HashMap<(u64, u64), u32>>>,
+    /// Files with a single link counted where they were found, sharded per worker
+    files: Vec<Mutex<HashSet<(u64, u64)>>>,
+    pending: Mutex<Vec<PendingDir>>,
+    followed: AtomicU64,
+    broken: AtomicU64,
+    loops: AtomicU64,
+}
+
+impl Follow {
+    pub fn new(shards: usize) -> Self {
+        Follow {
+            dirs: (0..shards).map(|_| Mutex::new(HashMap::new())).collect(),
+            files: (0..shards).map(|_| Mutex::new(HashSet::new())).collect(),
+            pending: Mutex::new(Vec::new()),
+            followed: AtomicU64::new(0),
+            broken: AtomicU64::new(0),
+            loops: AtomicU64::new(0),
+        }
+    }
+
+    /// Claim the directory `key` for node `id`. Returns the node that
+    /// already holds it, if it is another one.
+    pub fn claim(&self, key: (u64, u64), id: u32) -> Option<u32> {
+        // Inode numbers are spread well enough to pick a shard directly
+        let shard = (key.0 ^ key.1) as usize % self.dirs.len();
+        let mut dirs = self.dirs[shard].lock().unwrap_or_else(|e| e.into_inner());
+        let owner = *dirs.entry(key).or_insert(id);
+        (owner != id).then_some(owner)
+    }
+
+    /// Remember a file with a single link counted at its own path, so
+    /// symlinks to it add nothing.
+    pub fn count_file(&self, metadata: &Metadata) {
+        let shard = rayon::current_thread_index().unwrap_or(0) % self.files.len();
+        self.files[shard]
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .insert((metadata.dev, metadata.ino));
+    }
+
+    /// Every file recorded by [`Follow::count_file`].
+    pub fn counted_files(&mut self) -> HashSet<(u64, u64)> {
+        let mut counted = HashSet::new();
+        for shard in std::mem::take(&mut self.files) {
+            counted.extend(shard.into_inner().unwrap_or_else(|e| e.into_inner()));
+        }
+        counted
+    }
+
+    pub fn defer_dir(&self, dir: PendingDir) {
+        self.pending
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .push(dir);
+    }
+
+    /// Symlinked directories found since the last call, sorted by path so
+    /// that the first path to a directory is the one that counts it.
+    pub fn take_pending(&self) -> Vec<PendingDir> {
+        let mut pending =
+            std::mem::take(&mut *self.pending.lock().unwrap_or_else(|e| e.into_inner()));
+        pending.sort_by(|a, b| a.path.cmp(&b.path));
+        pending
+    }
+
+    pub fn followed(&self) {
+        self.followed.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn broken(&self) {
+        self.broken.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn looped(&self) {
+        self.loops.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn stats(&self) -> SymlinkStats {
+        SymlinkStats {
+            followed: self.followed.load(Ordering::Relaxed),
+            broken: self.broken.load(Ordering::Relaxed),
+            loops: self.loops.load(Ordering::Relaxed),
+        }
+    }
+}
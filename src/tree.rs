@@ -0,0 +1,527 @@
+use ahash::AHashMap;
+use std::collections::HashMap;
+use std::ffi::{OsStr, OsString};
+use std::path::{Component, Path, PathBuf};
+
+/// Sentinel used for "no node" in parent and sibling links.
+const NONE: u32 = u32::MAX;
+
+/// Identifier of a directory node inside a [`DirTree`].
+///
+/// Ids are only meaningful for the tree that produced them. A node's id is
+/// always greater than its parent's id.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct NodeId(u32);
+
+impl NodeId {
+    /// Position of this node in the tree's arena.
+    pub fn index(self) -> usize {
+        self.0 as usize
+    }
+}
+
+/// Interned directory name components, shared by every node with the same name.
+#[derive(Default, Clone)]
+struct Names {
+    lookup: AHashMap<Box<OsStr>, u32>,
+    names: Vec<Box<OsStr>>,
+}
+
+impl Names {
+    fn intern(&mut self, name: &OsStr) -> u32 {
+        if let Some(&id) = self.lookup.get(name) {
+            return id;
+        }
+        let id = self.names.len() as u32;
+        let boxed: Box<OsStr> = name.into();
+        self.names.push(boxed.clone());
+        self.lookup.insert(boxed, id);
+        id
+    }
+
+    fn get(&self, name: &OsStr) -> Option<u32> {
+        self.lookup.get(name).copied()
+    }
+
+    fn resolve(&self, id: u32) -> &OsStr {
+        &self.names[id as usize]
+    }
+}
+
+#[derive(Clone)]
+struct Node {
+    parent: u32,
+    name: u32,
+    first_child: u32,
+    next_sibling: u32,
+    own_bytes: u64,
+    own_files: u64,
+    own_modified: u64,
+    total_bytes: u64,
+    total_files: u64,
+    newest: u64,
+}
+
+/// Arena-backed directory tree holding aggregated sizes.
+///
+/// Each directory is stored once, with a link to its parent and an interned
+/// name component, instead of as a full owned path. Sizes are recorded per
+/// directory and rolled up into subtree totals by [`DirTree::aggregate`] in a
+/// single bottom-up pass. Full paths are only built on demand.
+///
+/// # Example
+///
+/// ```rust
+/// use std::path::Path;
+/// use rudu::DirTree;
+///
+/// let mut tree = DirTree::new("/data");
+/// tree.add_file(Path::new("/data/logs/app.log"), 300);
+/// tree.add_file(Path::new("/data/readme.txt"), 20);
+/// tree.aggregate();
+///
+/// let logs = tree.lookup(Path::new("/data/logs")).unwrap();
+/// assert_eq!(tree.size(logs), 300);
+/// assert_eq!(tree.size(tree.root()), 320);
+/// ```
+#[derive(Clone)]
+pub struct DirTree {
+    root_path: PathBuf,
+    nodes: Vec<Node>,
+    names: Names,
+    /// Child of each `(parent, name)` pair, so finding a subdirectory does
+    /// not walk the parent's sibling list
+    children: AHashMap<(u32, u32), u32>,
+}
+
+impl DirTree {
+    /// Create a tree containing only the root directory at `root`.
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        Self::with_capacity(root, 1)
+    }
+
+    /// Create a tree with room for `capacity` directories before reallocating.
+    pub fn with_capacity(root: impl Into<PathBuf>, capacity: usize) -> Self {
+        let mut names = Names::default();
+        let root_name = names.intern(OsStr::new(""));
+        let mut nodes = Vec::with_capacity(capacity.max(1));
+        nodes.push(Node::new(NONE, root_name));
+        DirTree {
+            root_path: root.into(),
+            nodes,
+            names,
+            children: AHashMap::with_capacity(capacity),
+        }
+    }
+
+    /// Id of the root directory.
+    pub fn root(&self) -> NodeId {
+        NodeId(0)
+    }
+
+    /// Path of the root directory.
+    pub fn root_path(&self) -> &Path {
+        &self.root_path
+    }
+
+    /// Number of directories in the tree, including the root.
+    pub fn len(&self) -> usize {
+        self.nodes.len()
+    }
+
+    /// Always `false`: a tree contains at least its root.
+    pub fn is_empty(&self) -> bool {
+        self.nodes.is_empty()
+    }
+
+    /// Iterate over every node id, parents before their children.
+    pub fn iter(&self) -> impl Iterator<Item = NodeId> + '_ {
+        (0..self.nodes.len() as u32).map(NodeId)
+    }
+
+    /// Parent of `id`, or `None` for the root.
+    pub fn parent(&self, id: NodeId) -> Option<NodeId> {
+        match self.node(id).parent {
+            NONE => None,
+            parent => Some(NodeId(parent)),
+        }
+    }
+
+    /// Iterate over the direct subdirectories of `id`.
+    pub fn children(&self, id: NodeId) -> impl Iterator<Item = NodeId> + '_ {
+        let mut next = self.node(id).first_child;
+        std::iter::from_fn(move || {
+            if next == NONE {
+                return None;
+            }
+            let current = next;
+            next = self.nodes[current as usize].next_sibling;
+            Some(NodeId(current))
+        })
+    }
+
+    /// Final path component of `id`; empty for the root.
+    pub fn name(&self, id: NodeId) -> &OsStr {
+        self.names.resolve(self.node(id).name)
+    }
+
+    /// Total bytes in the subtree rooted at `id`.
+    ///
+    /// Only meaningful after [`DirTree::aggregate`] has been called.
+    pub fn size(&self, id: NodeId) -> u64 {
+        self.node(id).total_bytes
+    }
+
+    /// Bytes of the files directly inside `id`, excluding subdirectories.
+    pub fn own_size(&self, id: NodeId) -> u64 {
+        self.node(id).own_bytes
+    }
+
+    /// Number of files in the subtree rooted at `id`.
+    ///
+    /// Only meaningful after [`DirTree::aggregate`] has been called.
+    pub fn file_count(&self, id: NodeId) -> u64 {
+        self.node(id).total_files
+    }
+
+    /// Newest modification time of a file in the subtree rooted at `id`, in
+    /// seconds since the Unix epoch, or `0` if none is known.
+    ///
+    /// Only meaningful after [`DirTree::aggregate`] has been called.
+    pub fn modified(&self, id: NodeId) -> u64 {
+        self.node(id).newest
+    }
+
+    /// Build the full path of `id`.
+    pub fn path(&self, id: NodeId) -> PathBuf {
+        let mut components = Vec::new();
+        let mut current = id;
+        while let Some(parent) = self.parent(current) {
+            components.push(self.name(current));
+            current = parent;
+        }
+
+        let mut path = self.root_path.clone();
+        for name in components.into_iter().rev() {
+            path.push(name);
+        }
+        path
+    }
+
+    /// Find the node for `path`, if it is the root or a directory below it.
+    pub fn lookup(&self, path: &Path) -> Option<NodeId> {
+        let relative = path.strip_prefix(&self.root_path).ok()?;
+        let mut current = self.root();
+        for component in relative.components() {
+            let name = match component {
+                Component::Normal(name) => self.names.get(name)?,
+                _ => return None,
+            };
+            current = self.child(current, name)?;
+        }
+        Some(current)
+    }
+
+    /// Append a new subdirectory called `name` under `parent`.
+    ///
+    /// No check is made for an existing child with the same name; use
+    /// [`DirTree::insert_dir`] when the directory may already be present.
+    pub fn add_dir(&mut self, parent: NodeId, name: &OsStr) -> NodeId {
+        let name = self.names.intern(name);
+        self.push_node(parent.0, name)
+    }
+
+    /// Return the node for `path`, creating it and any missing ancestors.
+    ///
+    /// Returns `None` if `path` is not under the root.
+    pub fn insert_dir(&mut self, path: &Path) -> Option<NodeId> {
+        let relative = path.strip_prefix(&self.root_path).ok()?.to_path_buf();
+        let mut current = self.root();
+        for component in relative.components() {
+            let name = match component {
+                Component::Normal(name) => name,
+                _ => return None,
+            };
+            let name_id = self.names.intern(name);
+            current = match self.child(current, name_id) {
+                Some(child) => child,
+                None => self.push_node(current.0, name_id),
+            };
+        }
+        Some(current)
+    }
+
+    /// Record a file of `bytes` at `path`, creating its parent directories.
+    ///
+    /// Returns `false` if the file's parent directory is not under the root.
+    pub fn add_file(&mut self, path: &Path, bytes: u64) -> bool {
+        let parent = match path.parent() {
+            Some(parent) => parent,
+            None => return false,
+        };
+        match self.insert_dir(parent) {
+            Some(id) => {
+                self.add_size(id, bytes, 1);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Add `bytes` spread over `files` files directly to the directory `id`.
+    pub fn add_size(&mut self, id: NodeId, bytes: u64, files: u64) {
+        let node = &mut self.nodes[id.index()];
+        node.own_bytes += bytes;
+        node.own_files += files;
+    }
+
+    /// Record that a file directly in `id` was modified at `modified`, in
+    /// seconds since the Unix epoch. The newest time recorded is kept.
+    pub fn add_modified(&mut self, id: NodeId, modified: u64) {
+        let node = &mut self.nodes[id.index()];
+        node.own_modified = node.own_modified.max(modified);
+    }
+
+    /// Roll per-directory sizes up into subtree totals.
+    ///
+    /// Children always have larger ids than their parents, so one reverse
+    /// pass over the arena visits every node after all of its descendants.
+    /// Safe to call again after further additions.
+    pub fn aggregate(&mut self) {
+        for node in &mut self.nodes {
+            node.total_bytes = node.own_bytes;
+            node.total_files = node.own_files;
+            node.newest = node.own_modified;
+        }
+        for index in (1..self.nodes.len()).rev() {
+            let (bytes, files, newest, parent) = {
+                let node = &self.nodes[index];
+                (
+                    node.total_bytes,
+                    node.total_files,
+                    node.newest,
+                    node.parent as usize,
+                )
+            };
+            let parent = &mut self.nodes[parent];
+            parent.total_bytes += bytes;
+            parent.total_files += files;
+            parent.newest = parent.newest.max(newest);
+        }
+    }
+
+    /// Convert to the flat path-to-size map returned by the `compute_dir_sizes*` functions.
+    pub fn to_size_map(&self) -> HashMap<PathBuf, u64> {
+        self.paths()
+            .into_iter()
+            .zip(self.nodes.iter())
+            .map(|(path, node)| (path, node.total_bytes))
+            .collect()
+    }
+
+    /// Convert to a flat map from each directory to the number of files in its subtree.
+    pub fn to_file_count_map(&self) -> HashMap<PathBuf, u64> {
+        self.paths()
+            .into_iter()
+            .zip(self.nodes.iter())
+            .map(|(path, node)| (path, node.total_files))
+            .collect()
+    }
+
+    /// Convert to a flat map from each directory to the newest modification
+    /// time in its subtree, see [`DirTree::modified`].
+    pub fn to_modified_map(&self) -> HashMap<PathBuf, u64> {
+        self.paths()
+            .into_iter()
+            .zip(self.nodes.iter())
+            .map(|(path, node)| (path, node.newest))
+            .collect()
+    }
+
+    /// The full path of every node, indexed by node id.
+    fn paths(&self) -> Vec<PathBuf> {
+        let mut paths: Vec<PathBuf> = Vec::with_capacity(self.nodes.len());
+        for (index, node) in self.nodes.iter().enumerate() {
+            let path = match node.parent {
+                NONE => self.root_path.clone(),
+                parent => paths[parent as usize].join(self.names.resolve(node.name)),
+            };
+            debug_assert_eq!(paths.len(), index);
+            paths.push(path);
+        }
+        paths
+    }
+
+    /// Build a tree from directory records whose ids are exactly `0..records.len()`.
+    ///
+    /// Record `0` is the root and every other record's parent must have a
+    /// smaller id. Used by the parallel scanner, which allocates ids up front.
+    pub(crate) fn from_records(root: PathBuf, mut records: Vec<DirRecord>) -> Self {
+        records.sort_unstable_by_key(|record| record.id);
+        let mut tree = DirTree::with_capacity(root, records.len());
+        for record in records {
+            let id = if record.id == 0 {
+                tree.root()
+            } else {
+                let name = tree.names.intern(&record.name);
+                tree.push_node(record.parent, name)
+            };
+            debug_assert_eq!(id.0, record.id);
+            tree.add_size(id, record.bytes, record.files);
+            tree.add_modified(id, record.modified);
+        }
+        tree
+    }
+
+    fn node(&self, id: NodeId) -> &Node {
+        &self.nodes[id.index()]
+    }
+
+    fn child(&self, parent: NodeId, name: u32) -> Option<NodeId> {
+        self.children.get(&(parent.0, name)).copied().map(NodeId)
+    }
+
+    fn push_node(&mut self, parent: u32, name: u32) -> NodeId {
+        let id = self.nodes.len() as u32;
+        let mut node = Node::new(parent, name);
+        let parent_node = &mut self.nodes[parent as usize];
+        node.next_sibling = parent_node.first_child;
+        parent_node.first_child = id;
+        self.nodes.push(node);
+        self.children.insert((parent, name), id);
+        NodeId(id)
+    }
+}
+
+impl Node {
+    fn new(parent: u32, name: u32) -> Self {
+        Node {
+            parent,
+            name,
+            first_child: NONE,
+            next_sibling: NONE,
+            own_bytes: 0,
+            own_files: 0,
+            own_modified: 0,
+            total_bytes: 0,
+            total_files: 0,
+            newest: 0,
+        }
+    }
+}
+
+/// One directory as seen by a scanner task, before the tree is assembled.
+pub(crate) struct DirRecord {
+    pub id: u32,
+    pub parent: u32,
+    pub name: OsString,
+    pub bytes: u64,
+    pub files: u64,
+    /// Newest modification time of the files directly inside
+    pub modified: u64,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_aggregate_and_lookup() {
+        let mut tree = DirTree::new("/root");
+        assert!(tree.add_file(Path::new("/root/a/b/one.bin"), 3));
+        assert!(tree.add_file(Path::new("/root/a/two.bin"), 4));
+        assert!(tree.add_file(Path::new("/root/c/three.bin"), 5));
+        assert!(!tree.add_file(Path::new("/elsewhere/four.bin"), 6));
+        tree.aggregate();
+
+        let a = tree.lookup(Path::new("/root/a")).unwrap();
+        let b = tree.lookup(Path::new("/root/a/b")).unwrap();
+        assert_eq!(tree.size(tree.root()), 12);
+        assert_eq!(tree.file_count(tree.root()), 3);
+        assert_eq!(tree.size(a), 7);
+        assert_eq!(tree.own_size(a), 4);
+        assert_eq!(tree.size(b), 3);
+        assert_eq!(tree.parent(b), Some(a));
+        assert_eq!(tree.path(b), PathBuf::from("/root/a/b"));
+        assert_eq!(tree.len(), 4);
+        assert!(tree.lookup(Path::new("/root/missing")).is_none());
+    }
+
+    #[test]
+    fn test_wide_directory_inserts_and_lookups() {
+        // Enough siblings that a linear scan per insert would take minutes
+        const WIDTH: usize = 200_000;
+        let mut tree = DirTree::new("/root");
+        for i in 0..WIDTH {
+            assert!(tree.add_file(&PathBuf::from(format!("/root/d{i}/file")), 1));
+        }
+        for i in (0..WIDTH).rev() {
+            tree.insert_dir(&PathBuf::from(format!("/root/d{i}")));
+        }
+        tree.aggregate();
+
+        assert_eq!(tree.len(), WIDTH + 1);
+        assert_eq!(tree.size(tree.root()), WIDTH as u64);
+        let last = tree.lookup(Path::new("/root/d199999")).unwrap();
+        assert_eq!(tree.file_count(last), 1);
+        assert_eq!(tree.children(tree.root()).count(), WIDTH);
+    }
+
+    #[test]
+    fn test_names_are_interned() {
+        let mut tree = DirTree::new("/root");
+        tree.insert_dir(Path::new("/root/x/src"));
+        tree.insert_dir(Path::new("/root/y/src"));
+        tree.insert_dir(Path::new("/root/x/src"));
+
+        assert_eq!(tree.len(), 5);
+        // "", "x", "src", "y"
+        assert_eq!(tree.names.names.len(), 4);
+    }
+
+    #[test]
+    fn test_to_size_map_matches_paths() {
+        let mut tree = DirTree::new("/root");
+        tree.add_file(Path::new("/root/a/b/file"), 10);
+        tree.aggregate();
+
+        let map = tree.to_size_map();
+        assert_eq!(map.len(), 3);
+        assert_eq!(map[Path::new("/root")], 10);
+        assert_eq!(map[Path::new("/root/a")], 10);
+        assert_eq!(map[Path::new("/root/a/b")], 10);
+    }
+
+    #[test]
+    fn test_to_file_count_map_counts_subtrees() {
+        let mut tree = DirTree::new("/root");
+        tree.add_file(Path::new("/root/a/b/file"), 10);
+        tree.add_file(Path::new("/root/a/other"), 5);
+        tree.add_file(Path::new("/root/top"), 1);
+        tree.aggregate();
+
+        let map = tree.to_file_count_map();
+        assert_eq!(map.len(), 3);
+        assert_eq!(map[Path::new("/root")], 3);
+        assert_eq!(map[Path::new("/root/a")], 2);
+        assert_eq!(map[Path::new("/root/a/b")], 1);
+    }
+
+    #[test]
+    fn test_modified_is_newest_in_subtree() {
+        let mut tree = DirTree::new("/root");
+        let a = tree.insert_dir(Path::new("/root/a")).unwrap();
+        let b = tree.insert_dir(Path::new("/root/b")).unwrap();
+        let deep = tree.insert_dir(Path::new("/root/a/deep")).unwrap();
+        tree.add_modified(a, 100);
+        tree.add_modified(deep, 300);
+        tree.add_modified(deep, 200);
+        tree.aggregate();
+
+        assert_eq!(tree.modified(deep), 300);
+        assert_eq!(tree.modified(a), 300);
+        assert_eq!(tree.modified(b), 0);
+        assert_eq!(tree.modified(tree.root()), 300);
+        assert_eq!(tree.to_modified_map()[Path::new("/root/a")], 300);
+    }
+}
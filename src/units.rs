@@ -0,0 +1,241 @@
+//! Size units: how sizes are shown, and parsing sizes such as `1.5G` or `500MB`.
+//!
+//! Unit letters follow `du`: `K`, `M`, `G`, `T`, `P` and `E` alone or with
+//! `iB` are powers of 1024, with `B` they are powers of 1000. Letters are
+//! case-insensitive.
+
+use humansize::{format_size, BINARY, DECIMAL};
+
+/// How sizes are shown.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum SizeFormat {
+    /// Human-readable in powers of 1000 (kB, MB, GB)
+    #[default]
+    Decimal,
+    /// Human-readable in powers of 1024 (KiB, MiB, GiB)
+    Binary,
+    /// Whole number of blocks, rounded up
+    Blocks(BlockSize),
+    /// Exact byte count
+    Bytes,
+}
+
+impl SizeFormat {
+    /// Show `bytes` in this format.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use rudu::units::{parse_block_size, SizeFormat};
+    /// assert_eq!(SizeFormat::Decimal.format(1_500_000), "1.50 MB");
+    /// assert_eq!(SizeFormat::Binary.format(1_572_864), "1.50 MiB");
+    /// let megabytes = SizeFormat::Blocks(parse_block_size("M").unwrap());
+    /// assert_eq!(megabytes.format(1_572_864), "2M");
+    /// assert_eq!(SizeFormat::Bytes.format(1_572_864), "1572864");
+    /// ```
+    pub fn format(&self, bytes: u64) -> String {
+        match self {
+            SizeFormat::Decimal => format_size(bytes, DECIMAL),
+            SizeFormat::Binary => format_size(bytes, BINARY),
+            SizeFormat::Blocks(block) => {
+                format!("{}{}", bytes.div_ceil(block.bytes), block.label)
+            }
+            SizeFormat::Bytes => bytes.to_string(),
+        }
+    }
+
+    /// Bytes in a kilobyte (`power` 1), megabyte (2) and so on, in powers of
+    /// 1024 for binary formats and of 1000 otherwise.
+    pub fn multiple(&self, power: u32) -> u64 {
+        let binary = match self {
+            SizeFormat::Binary => true,
+            SizeFormat::Blocks(block) => block.binary,
+            SizeFormat::Decimal | SizeFormat::Bytes => false,
+        };
+        (if binary { 1024u64 } else { 1000 }).pow(power)
+    }
+}
+
+/// The block size of [`SizeFormat::Blocks`], see [`parse_block_size`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct BlockSize {
+    /// Bytes per block
+    pub bytes: u64,
+    /// Unit shown after each count, empty for a plain number of bytes
+    pub label: &'static str,
+    /// Whether the block is a power of 1024
+    pub binary: bool,
+}
+
+/// The recognized units: binary letter, binary label, decimal label.
+const UNITS: [(&str, &str, &str); 6] = [
+    ("K", "KiB", "KB"),
+    ("M", "MiB", "MB"),
+    ("G", "GiB", "GB"),
+    ("T", "TiB", "TB"),
+    ("P", "PiB", "PB"),
+    ("E", "EiB", "EB"),
+];
+
+/// A unit suffix as `(bytes, label, binary)`; bytes have an empty label.
+fn parse_unit(unit: &str) -> Option<(u64, &'static str, bool)> {
+    if unit.is_empty() || unit.eq_ignore_ascii_case("b") {
+        return Some((1, "", false));
+    }
+    UNITS
+        .iter()
+        .zip(1..)
+        .find_map(|(&(letter, binary, decimal), power)| {
+            if unit.eq_ignore_ascii_case(letter) || unit.eq_ignore_ascii_case(binary) {
+                Some((1024u64.pow(power), letter, true))
+            } else if unit.eq_ignore_ascii_case(decimal) {
+                Some((1000u64.pow(power), decimal, false))
+            } else {
+                None
+            }
+        })
+}
+
+/// Split `value` into its number and its unit suffix.
+fn split_number(value: &str) -> (&str, &str) {
+    let value = value.trim();
+    let split = value
+        .find(|c: char| !c.is_ascii_digit() && c != '.')
+        .unwrap_or(value.len());
+    let (number, unit) = value.split_at(split);
+    (number, unit.trim())
+}
+
+/// Parse a size such as `4096`, `1.5G`, `500MB` or `2 GiB` into bytes;
+/// a bare number is bytes.
+pub fn parse_size(value: &str) -> Result<u64, String> {
+    let (number, unit) = split_number(value);
+    let (multiple, _, _) = parse_unit(unit).ok_or_else(|| {
+        format!("unknown size unit '{unit}' (use B, K, M, G, T, P or E, optionally with iB or B)")
+    })?;
+    if let Ok(whole) = number.parse::<u64>() {
+        return whole
+            .checked_mul(multiple)
+            .ok_or_else(|| format!("size '{}' is too large", value.trim()));
+    }
+    let number: f64 = number
+        .parse()
+        .map_err(|_| format!("invalid size '{}'", value.trim()))?;
+    let bytes = (number * multiple as f64).round();
+    if bytes >= u64::MAX as f64 {
+        return Err(format!("size '{}' is too large", value.trim()));
+    }
+    Ok(bytes as u64)
+}
+
+/// Parse a block size for [`SizeFormat::Blocks`]: a unit such as `K`, `M`
+/// or `GB`, optionally preceded by a whole number, or a number of bytes.
+///
+/// Counts are labelled with the unit when the block is exactly one unit,
+/// as in `12M`, and shown as plain numbers otherwise.
+pub fn parse_block_size(value: &str) -> Result<BlockSize, String> {
+    let (number, unit) = split_number(value);
+    let (multiple, label, binary) = parse_unit(unit).ok_or_else(|| {
+        format!(
+            "unknown block size unit '{unit}' (use K, M, G, T, P or E, optionally with iB or B)"
+        )
+    })?;
+    let count = match number {
+        "" if !unit.is_empty() => 1,
+        _ => number
+            .parse::<u64>()
+            .map_err(|_| format!("invalid block size '{}'", value.trim()))?,
+    };
+    let bytes = count
+        .checked_mul(multiple)
+        .filter(|&bytes| bytes > 0)
+        .ok_or_else(|| format!("invalid block size '{}'", value.trim()))?;
+    Ok(BlockSize {
+        bytes,
+        label: if count == 1 { label } else { "" },
+        binary,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_size() {
+        assert_eq!(parse_size("4096"), Ok(4096));
+        assert_eq!(parse_size("10B"), Ok(10));
+        assert_eq!(parse_size("1K"), Ok(1024));
+        assert_eq!(parse_size("1kib"), Ok(1024));
+        assert_eq!(parse_size("1kB"), Ok(1000));
+        assert_eq!(parse_size("1.5G"), Ok(1_610_612_736));
+        assert_eq!(parse_size("2 GB"), Ok(2_000_000_000));
+        assert_eq!(parse_size(" 500MB "), Ok(500_000_000));
+        assert_eq!(parse_size("0.5"), Ok(1));
+        assert_eq!(
+            parse_size("16E"),
+            Err("size '16E' is too large".to_string())
+        );
+        assert!(parse_size("1.2.3M").is_err());
+        assert!(parse_size("12Q").is_err());
+        assert!(parse_size("M").is_err());
+        assert!(parse_size("").is_err());
+    }
+
+    #[test]
+    fn test_parse_block_size() {
+        let mega = parse_block_size("M").unwrap();
+        assert_eq!(
+            mega,
+            BlockSize {
+                bytes: 1 << 20,
+                label: "M",
+                binary: true
+            }
+        );
+        assert_eq!(parse_block_size("MiB").unwrap(), mega);
+        assert_eq!(parse_block_size("1m").unwrap(), mega);
+
+        let kilo = parse_block_size("kb").unwrap();
+        assert_eq!((kilo.bytes, kilo.label, kilo.binary), (1000, "KB", false));
+
+        // Multiples are shown without a unit
+        let sectors = parse_block_size("512").unwrap();
+        assert_eq!((sectors.bytes, sectors.label), (512, ""));
+        assert_eq!(parse_block_size("4K").unwrap().label, "");
+
+        assert!(parse_block_size("0").is_err());
+        assert!(parse_block_size("0M").is_err());
+        assert!(parse_block_size("1.5M").is_err());
+        assert!(parse_block_size("").is_err());
+        assert!(parse_block_size("X").is_err());
+    }
+
+    #[test]
+    fn test_format() {
+        assert_eq!(SizeFormat::Decimal.format(999), "999 B");
+        assert_eq!(SizeFormat::Decimal.format(1_000_000_000), "1 GB");
+        assert_eq!(SizeFormat::Binary.format(1 << 30), "1 GiB");
+        assert_eq!(SizeFormat::Bytes.format(123_456_789), "123456789");
+
+        // Block counts round up, so anything non-empty is at least one block
+        let kilo = SizeFormat::Blocks(parse_block_size("K").unwrap());
+        assert_eq!(kilo.format(0), "0K");
+        assert_eq!(kilo.format(1), "1K");
+        assert_eq!(kilo.format(2048), "2K");
+        assert_eq!(kilo.format(2049), "3K");
+        let sectors = SizeFormat::Blocks(parse_block_size("512").unwrap());
+        assert_eq!(sectors.format(1024), "2");
+    }
+
+    #[test]
+    fn test_multiple_follows_the_unit_base() {
+        assert_eq!(SizeFormat::Decimal.multiple(3), 1_000_000_000);
+        assert_eq!(SizeFormat::Bytes.multiple(2), 1_000_000);
+        assert_eq!(SizeFormat::Binary.multiple(3), 1 << 30);
+        let mega = SizeFormat::Blocks(parse_block_size("M").unwrap());
+        assert_eq!(mega.multiple(2), 1 << 20);
+        let megabytes = SizeFormat::Blocks(parse_block_size("MB").unwrap());
+        assert_eq!(megabytes.multiple(2), 1_000_000);
+    }
+}
@@ -0,0 +1,149 @@
+use crate::filesystem::Metadata;
+use std::path::Path;
+
+/// A regular file reached by a scan, as passed to [`Aggregator::visit`].
+#[derive(Clone, Copy, Debug)]
+pub struct FileEntry<'a> {
+    /// Full path of the file
+    pub path: &'a Path,
+    /// Metadata of the file
+    pub metadata: &'a Metadata,
+    /// Bytes the file contributes to directory totals: its size, or for a
+    /// file with several hard links, what the scanner's
+    /// [`HardlinkPolicy`](crate::HardlinkPolicy) assigns to this path
+    pub counted_bytes: u64,
+}
+
+/// Custom per-file aggregation run alongside a scan.
+///
+/// [`Scanner::scan_with`](crate::Scanner::scan_with) calls [`Aggregator::visit`]
+/// for every regular file on the scan's worker threads. Each worker keeps its
+/// own accumulator, created with [`Aggregator::init`], and the accumulators
+/// are combined with [`Aggregator::merge`] once the walk is over, so `visit`
+/// never contends with other workers.
+///
+/// # Example
+///
+/// ```rust
+/// use std::collections::HashMap;
+/// use rudu::visit::{Aggregator, FileEntry};
+/// use rudu::Scanner;
+///
+/// /// Bytes per file extension
+/// struct ByExtension;
+///
+/// impl Aggregator for ByExtension {
+///     type Output = HashMap<String, u64>;
+///
+///     fn init(&self) -> Self::Output {
+///         HashMap::new()
+///     }
+///
+///     fn visit(&self, acc: &mut Self::Output, file: &FileEntry<'_>) {
+///         let ext = file.path.extension().map(|e| e.to_string_lossy().into_owned());
+///         *acc.entry(ext.unwrap_or_default()).or_insert(0) += file.counted_bytes;
+///     }
+///
+///     fn merge(&self, mut a: Self::Output, b: Self::Output) -> Self::Output {
+///         for (ext, bytes) in b {
+///             *a.entry(ext).or_insert(0) += bytes;
+///         }
+///         a
+///     }
+/// }
+///
+/// let (result, by_extension) = Scanner::new("/tmp").scan_with(&ByExtension);
+/// let total: u64 = by_extension.values().sum();
+/// assert_eq!(total, result.tree.size(result.tree.root()));
+/// ```
+pub trait Aggregator: Sync {
+    /// Accumulated statistics
+    type Output: Send;
+
+    /// Create an empty accumulator.
+    fn init(&self) -> Self::Output;
+
+    /// Add one file to `acc`.
+    fn visit(&self, acc: &mut Self::Output, file: &FileEntry<'_>);
+
+    /// Combine two accumulators.
+    fn merge(&self, a: Self::Output, b: Self::Output) -> Self::Output;
+
+    /// Whether to descend into the subdirectory at `path`.
+    ///
+    /// Skipped directories are left out of the scan entirely, including
+    /// [`ScanResult::tree`](crate::ScanResult::tree). The scan root is always read.
+    fn enter_dir(&self, _path: &Path) -> bool {
+        true
+    }
+}
+
+/// Type-erased hooks for an [`Aggregator`], shared with the scan workers.
+#[derive(Clone, Copy)]
+pub(crate) struct Visitor<'a> {
+    pub file: &'a (dyn Fn(&FileEntry<'_>) + Sync),
+    pub enter_dir: &'a (dyn Fn(&Path) -> bool + Sync),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::filesystem::MemoryFs;
+    use crate::Scanner;
+    use std::collections::HashMap;
+    use std::path::PathBuf;
+
+    /// Counted bytes per top-level project, skipping `node_modules`
+    struct PerProject;
+
+    impl Aggregator for PerProject {
+        type Output = HashMap<PathBuf, u64>;
+
+        fn init(&self) -> Self::Output {
+            HashMap::new()
+        }
+
+        fn visit(&self, acc: &mut Self::Output, file: &FileEntry<'_>) {
+            let project = file.path.strip_prefix("/src").unwrap().iter().next();
+            *acc.entry(PathBuf::from(project.unwrap())).or_insert(0) += file.counted_bytes;
+        }
+
+        fn merge(&self, mut a: Self::Output, b: Self::Output) -> Self::Output {
+            for (project, bytes) in b {
+                *a.entry(project).or_insert(0) += bytes;
+            }
+            a
+        }
+
+        fn enter_dir(&self, path: &Path) -> bool {
+            path.file_name() != Some("node_modules".as_ref())
+        }
+    }
+
+    #[test]
+    fn test_scan_with_aggregator() {
+        let mut fs = MemoryFs::new();
+        for project in 0..20 {
+            for file in 0..10 {
+                fs.add_file(format!("/src/p{project}/lib/f{file}"), project + 1);
+            }
+            fs.add_file(format!("/src/p{project}/node_modules/dep/big"), 1_000);
+        }
+
+        let (result, per_project) = Scanner::new("/src").filesystem(fs).scan_with(&PerProject);
+
+        assert_eq!(per_project.len(), 20);
+        assert_eq!(per_project[Path::new("p0")], 10);
+        assert_eq!(per_project[Path::new("p19")], 200);
+        // Skipped directories are left out of the tree as well
+        assert_eq!(result.total_files(), 200);
+        assert_eq!(
+            per_project.values().sum::<u64>(),
+            result.tree.size(result.tree.root())
+        );
+        assert!(result
+            .tree
+            .lookup(Path::new("/src/p0/node_modules"))
+            .is_none());
+    }
+}